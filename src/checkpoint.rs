@@ -0,0 +1,206 @@
+use std::fs;
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+
+use futures::{pin_mut, Stream, TryStreamExt};
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+
+use crate::{to_relative_uri, RecordSink};
+
+// Save a checkpoint at least this often within a page, as one page of pull
+// requests may expand into thousands of records.
+const CHECKPOINT_INTERVAL: u64 = 100;
+
+// Where an interrupted extraction left off.
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
+pub struct Checkpoint {
+    // Repository being extracted when extracting more than one
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub repository: Option<String>,
+    // Route of the listing page being written, `None` before the first page
+    pub page: Option<String>,
+    // Records from `page` that have already been written
+    pub skip: u64,
+    // Records written in total
+    pub records: u64,
+    // Length of the output file, so that a partly written row can be cut off
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub output_len: Option<u64>,
+}
+
+impl Checkpoint {
+    pub fn load(path: impl AsRef<Path>) -> anyhow::Result<Self> {
+        Ok(serde_json::from_slice(&fs::read(path)?)?)
+    }
+
+    // Replaces the file as a whole, so a crash never leaves half a checkpoint
+    pub fn save(&self, path: impl AsRef<Path>) -> anyhow::Result<()> {
+        let path = path.as_ref();
+        let tmp = path.with_extension("tmp");
+        fs::write(&tmp, serde_json::to_vec(self)?)?;
+        fs::rename(&tmp, path)?;
+        Ok(())
+    }
+}
+
+// Tracks the page of a fetcher's top-level listing that is being extracted.
+// A fetcher given a cursor that already points at a page starts the listing
+// from there instead of from the first page.
+#[derive(Debug, Clone, Default)]
+pub struct Cursor(Arc<Mutex<Option<String>>>);
+
+impl Cursor {
+    pub fn starting_at(page: Option<String>) -> Self {
+        Self(Arc::new(Mutex::new(page)))
+    }
+
+    pub fn page(&self) -> Option<String> {
+        self.0.lock().unwrap().clone()
+    }
+
+    fn set(&self, page: String) {
+        *self.0.lock().unwrap() = Some(page);
+    }
+
+    // Same as `crate::pages`, but starts from the cursor's page if it has one
+    // and moves the cursor along as pages are yielded.
+    pub fn pages<'a, M: DeserializeOwned + 'a>(
+        &'a self,
+        octocrab: &'a octocrab::Octocrab,
+        route: String,
+    ) -> impl Stream<Item = crate::Result<octocrab::Page<M>>> + 'a {
+        async_stream::try_stream! {
+            let mut route = Some(self.page().unwrap_or(route));
+            while let Some(current) = route {
+                let mut page: octocrab::Page<M> = octocrab.get(&current, None::<&()>).await?;
+                route = page
+                    .next
+                    .take()
+                    .map(|next| to_relative_uri(next).to_string());
+                self.set(current);
+                yield page;
+            }
+        }
+    }
+}
+
+// Like `write_stream`, but hands a checkpoint to `save` whenever the cursor
+// moves to another page, every `CHECKPOINT_INTERVAL` records and when the
+// stream fails, after flushing the sink. Records already written according
+// to `checkpoint` are skipped.
+pub async fn write_resumable<R, S, T, F>(
+    stream: T,
+    sink: &mut S,
+    cursor: &Cursor,
+    checkpoint: &mut Checkpoint,
+    mut save: F,
+) -> anyhow::Result<()>
+where
+    S: RecordSink<R> + ?Sized,
+    T: Stream<Item = crate::Result<R>>,
+    F: FnMut(&Checkpoint) -> anyhow::Result<()>,
+{
+    pin_mut!(stream);
+    let mut skip = checkpoint.skip;
+    loop {
+        let record = match stream.try_next().await {
+            Ok(Some(record)) => record,
+            Ok(None) => break,
+            Err(e) => {
+                sink.flush()?;
+                save(checkpoint)?;
+                return Err(e.into());
+            }
+        };
+        let page = cursor.page();
+        if page != checkpoint.page {
+            sink.flush()?;
+            checkpoint.page = page;
+            checkpoint.skip = 0;
+            skip = 0;
+            save(checkpoint)?;
+        }
+        if skip > 0 {
+            skip -= 1;
+            continue;
+        }
+
+        sink.write(record)?;
+        checkpoint.records += 1;
+        checkpoint.skip += 1;
+        if checkpoint.skip % CHECKPOINT_INTERVAL == 0 {
+            sink.flush()?;
+            save(checkpoint)?;
+        }
+    }
+    sink.flush()?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Three pages of three records; fails after `fail_after` records if given
+    fn records(
+        cursor: &Cursor,
+        fail_after: Option<usize>,
+    ) -> impl Stream<Item = crate::Result<u32>> + '_ {
+        async_stream::try_stream! {
+            let start = cursor.page().map(|p| p.parse::<u32>().unwrap()).unwrap_or(0);
+            let mut yielded = 0;
+            for page in start..3 {
+                cursor.set(page.to_string());
+                for i in 0..3 {
+                    if Some(yielded) == fail_after {
+                        Err(octocrab::Error::Other {
+                            source: "connection reset".into(),
+                            backtrace: std::backtrace::Backtrace::capture(),
+                        })?;
+                    }
+                    yielded += 1;
+                    yield page * 3 + i;
+                }
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn test_resume_after_failure() {
+        let mut written: Vec<u32> = vec![];
+        let mut saved: Vec<Checkpoint> = vec![];
+
+        let cursor = Cursor::default();
+        let mut checkpoint = Checkpoint::default();
+        let result = write_resumable(
+            records(&cursor, Some(5)),
+            &mut written,
+            &cursor,
+            &mut checkpoint,
+            |c| {
+                saved.push(c.clone());
+                Ok(())
+            },
+        )
+        .await;
+        assert!(result.is_err());
+        assert_eq!(written, vec![0, 1, 2, 3, 4]);
+        let last = saved.last().unwrap();
+        assert_eq!(last.page.as_deref(), Some("1"));
+        assert_eq!(last.skip, 2);
+        assert_eq!(last, &checkpoint);
+
+        let cursor = Cursor::starting_at(checkpoint.page.clone());
+        write_resumable(
+            records(&cursor, None),
+            &mut written,
+            &cursor,
+            &mut checkpoint,
+            |_| Ok(()),
+        )
+        .await
+        .unwrap();
+        assert_eq!(written, (0..9).collect::<Vec<u32>>());
+        assert_eq!(checkpoint.records, 9);
+    }
+}
@@ -0,0 +1,416 @@
+use futures::Stream;
+use serde::de::DeserializeOwned;
+use serde::*;
+use url::Url;
+
+use crate::checkpoint::Cursor;
+use crate::commits::CommitWalker;
+use crate::*;
+
+type DateTime = chrono::DateTime<chrono::Utc>;
+
+// octocrab's Page does not know the attributes the checks API wraps its
+// listings in, so they are taken here and paged by number instead
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+struct Listing<T> {
+    total_count: u64,
+    #[serde(alias = "check_suites", alias = "check_runs")]
+    items: Vec<T>,
+}
+
+async fn list<T: DeserializeOwned>(
+    octocrab: &octocrab::Octocrab,
+    route: &str,
+) -> octocrab::Result<Vec<T>> {
+    let mut items = vec![];
+    for page in 1.. {
+        let route = format!("{}&page={}", route, page);
+        let listing: Listing<T> = octocrab.get(route, None::<&()>).await?;
+        let last = listing.items.is_empty();
+        items.extend(listing.items);
+        if last || items.len() as u64 >= listing.total_count {
+            break;
+        }
+    }
+    Ok(items)
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct App {
+    pub id: i64,
+    pub slug: Option<String>,
+    pub name: String,
+}
+
+// ref: https://docs.github.com/en/rest/checks/suites
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[non_exhaustive]
+pub struct CheckSuite {
+    pub id: i64,
+    pub node_id: String,
+    pub url: Url,
+    pub head_branch: Option<String>,
+    pub head_sha: String,
+    pub status: Option<String>,
+    pub conclusion: Option<String>,
+    pub app: Option<App>,
+    #[serde(default)]
+    pub latest_check_runs_count: Option<i64>,
+    pub created_at: Option<DateTime>,
+    pub updated_at: Option<DateTime>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct CheckRunOutput {
+    pub title: Option<String>,
+    pub annotations_count: Option<i64>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct CheckSuiteId {
+    pub id: i64,
+}
+
+// ref: https://docs.github.com/en/rest/checks/runs
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[non_exhaustive]
+pub struct CheckRun {
+    pub id: i64,
+    pub node_id: String,
+    pub name: String,
+    pub head_sha: String,
+    pub external_id: Option<String>,
+    pub url: Url,
+    pub html_url: Option<String>,
+    pub details_url: Option<String>,
+    pub status: String,
+    pub conclusion: Option<String>,
+    pub started_at: Option<DateTime>,
+    pub completed_at: Option<DateTime>,
+    pub output: Option<CheckRunOutput>,
+    pub check_suite: Option<CheckSuiteId>,
+    pub app: Option<App>,
+}
+
+record! {
+    #[table(name = "check_suites")]
+    #[derive(Serialize, Debug)]
+    pub struct CheckSuiteRec {
+        pub id: i64,
+        pub node_id: String,
+        pub url: Url,
+        pub head_branch: Option<String>,
+        pub head_sha: String,
+        pub status: Option<String>,
+        pub conclusion: Option<String>,
+        pub app_id: Option<i64>,
+        pub app_slug: Option<String>,
+        pub app_name: Option<String>,
+        pub latest_check_runs_count: Option<i64>,
+        pub created_at: Option<DateTime>,
+        pub updated_at: Option<DateTime>,
+
+        pub sdc_repository: String,
+    }
+}
+
+impl RepositryAware for CheckSuiteRec {
+    fn set_repository(&mut self, name: String) {
+        self.sdc_repository = name;
+    }
+}
+
+impl From<CheckSuite> for CheckSuiteRec {
+    fn from(from: CheckSuite) -> Self {
+        Self {
+            id: from.id,
+            node_id: from.node_id,
+            url: from.url,
+            head_branch: from.head_branch,
+            head_sha: from.head_sha,
+            status: from.status,
+            conclusion: from.conclusion,
+            app_id: from.app.as_ref().map(|a| a.id),
+            app_slug: from.app.as_ref().and_then(|a| a.slug.clone()),
+            app_name: from.app.map(|a| a.name),
+            latest_check_runs_count: from.latest_check_runs_count,
+            created_at: from.created_at,
+            updated_at: from.updated_at,
+
+            sdc_repository: String::default(),
+        }
+    }
+}
+
+record! {
+    #[table(name = "check_runs")]
+    #[derive(Serialize, Debug)]
+    pub struct CheckRunRec {
+        pub id: i64,
+        pub node_id: String,
+        pub name: String,
+        pub head_sha: String,
+        pub external_id: Option<String>,
+        pub url: Url,
+        pub html_url: Option<String>,
+        pub details_url: Option<String>,
+        pub status: String,
+        pub conclusion: Option<String>,
+        pub started_at: Option<DateTime>,
+        pub completed_at: Option<DateTime>,
+        pub output_title: Option<String>,
+        pub annotations_count: Option<i64>,
+        pub check_suite_id: Option<i64>,
+        pub app_id: Option<i64>,
+        pub app_slug: Option<String>,
+        pub app_name: Option<String>,
+
+        pub sdc_repository: String,
+    }
+}
+
+impl RepositryAware for CheckRunRec {
+    fn set_repository(&mut self, name: String) {
+        self.sdc_repository = name;
+    }
+}
+
+impl From<CheckRun> for CheckRunRec {
+    fn from(from: CheckRun) -> Self {
+        Self {
+            id: from.id,
+            node_id: from.node_id,
+            name: from.name,
+            head_sha: from.head_sha,
+            external_id: from.external_id,
+            url: from.url,
+            html_url: from.html_url,
+            details_url: from.details_url,
+            status: from.status,
+            conclusion: from.conclusion,
+            started_at: from.started_at,
+            completed_at: from.completed_at,
+            output_title: from.output.as_ref().and_then(|o| o.title.clone()),
+            annotations_count: from.output.and_then(|o| o.annotations_count),
+            check_suite_id: from.check_suite.map(|s| s.id),
+            app_id: from.app.as_ref().map(|a| a.id),
+            app_slug: from.app.as_ref().and_then(|a| a.slug.clone()),
+            app_name: from.app.map(|a| a.name),
+
+            sdc_repository: String::default(),
+        }
+    }
+}
+
+// Both fetchers walk the commits of the default branch since `since` and
+// list the checks of each
+pub struct CheckSuiteFetcher {
+    owner: String,
+    name: String,
+    octocrab: octocrab::Octocrab,
+    commits: CommitWalker,
+}
+
+pub struct CheckRunFetcher {
+    owner: String,
+    name: String,
+    octocrab: octocrab::Octocrab,
+    commits: CommitWalker,
+}
+
+impl CheckSuiteFetcher {
+    pub fn new(
+        owner: String,
+        name: String,
+        since: Option<DateTime>,
+        octocrab: octocrab::Octocrab,
+    ) -> Self {
+        Self {
+            commits: CommitWalker::new(owner.clone(), name.clone(), since, octocrab.clone()),
+            owner,
+            name,
+            octocrab,
+        }
+    }
+
+    pub fn with_cursor(mut self, cursor: Cursor) -> Self {
+        self.commits = self.commits.with_cursor(cursor);
+        self
+    }
+
+    // Commits whose check suites are fetched at a time
+    pub fn with_concurrency(mut self, concurrency: usize) -> Self {
+        self.commits = self.commits.with_concurrency(concurrency);
+        self
+    }
+
+    // Log and leave out a commit whose check suites cannot be fetched instead
+    // of failing
+    pub fn with_skip_errors(mut self, yes: bool) -> Self {
+        self.commits = self.commits.with_skip_errors(yes);
+        self
+    }
+
+    fn reponame(&self) -> String {
+        format!("{}/{}", self.owner, self.name)
+    }
+
+    async fn commit_check_suites(&self, sha: String) -> crate::Result<Vec<CheckSuiteRec>> {
+        let query = Params::default().to_query()?;
+        let route = format!(
+            "/repos/{owner}/{repo}/commits/{sha}/check-suites?{query}",
+            owner = &self.owner,
+            repo = &self.name,
+            sha = sha,
+            query = query,
+        );
+        let suites = list::<CheckSuite>(&self.octocrab, &route).await?;
+        Ok(suites
+            .into_iter()
+            .map(|suite| {
+                let mut suite: CheckSuiteRec = suite.into();
+                suite.set_repository(self.reponame());
+                suite
+            })
+            .collect())
+    }
+
+    pub fn stream(&self) -> impl Stream<Item = crate::Result<CheckSuiteRec>> + '_ {
+        self.commits
+            .walk("check suites", move |sha| self.commit_check_suites(sha))
+    }
+
+    pub async fn fetch<S: RecordSink<CheckSuiteRec> + ?Sized>(
+        &self,
+        sink: &mut S,
+    ) -> crate::Result<()> {
+        write_stream(self.stream(), sink).await
+    }
+}
+
+impl CheckRunFetcher {
+    pub fn new(
+        owner: String,
+        name: String,
+        since: Option<DateTime>,
+        octocrab: octocrab::Octocrab,
+    ) -> Self {
+        Self {
+            commits: CommitWalker::new(owner.clone(), name.clone(), since, octocrab.clone()),
+            owner,
+            name,
+            octocrab,
+        }
+    }
+
+    pub fn with_cursor(mut self, cursor: Cursor) -> Self {
+        self.commits = self.commits.with_cursor(cursor);
+        self
+    }
+
+    // Commits whose check runs are fetched at a time
+    pub fn with_concurrency(mut self, concurrency: usize) -> Self {
+        self.commits = self.commits.with_concurrency(concurrency);
+        self
+    }
+
+    // Log and leave out a commit whose check runs cannot be fetched instead
+    // of failing
+    pub fn with_skip_errors(mut self, yes: bool) -> Self {
+        self.commits = self.commits.with_skip_errors(yes);
+        self
+    }
+
+    fn reponame(&self) -> String {
+        format!("{}/{}", self.owner, self.name)
+    }
+
+    async fn commit_check_runs(&self, sha: String) -> crate::Result<Vec<CheckRunRec>> {
+        // Not only the latest run of each name, but reruns too
+        let param = Params {
+            filter: Some("all".to_string()),
+            ..Default::default()
+        };
+        let query = param.to_query()?;
+        let route = format!(
+            "/repos/{owner}/{repo}/commits/{sha}/check-runs?{query}",
+            owner = &self.owner,
+            repo = &self.name,
+            sha = sha,
+            query = query,
+        );
+        let runs = list::<CheckRun>(&self.octocrab, &route).await?;
+        Ok(runs
+            .into_iter()
+            .map(|run| {
+                let mut run: CheckRunRec = run.into();
+                run.set_repository(self.reponame());
+                run
+            })
+            .collect())
+    }
+
+    pub fn stream(&self) -> impl Stream<Item = crate::Result<CheckRunRec>> + '_ {
+        self.commits
+            .walk("check runs", move |sha| self.commit_check_runs(sha))
+    }
+
+    pub async fn fetch<S: RecordSink<CheckRunRec> + ?Sized>(
+        &self,
+        sink: &mut S,
+    ) -> crate::Result<()> {
+        write_stream(self.stream(), sink).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_convert_check_run_listing() -> Result<(), Box<dyn std::error::Error>> {
+        let listing: Listing<CheckRun> = serde_json::from_str(
+            r#"{
+                "total_count": 1,
+                "check_runs": [{
+                    "id": 4,
+                    "node_id": "MDg6Q2hlY2tSdW40",
+                    "name": "mighty_readme",
+                    "head_sha": "ce587453ced02b1526dfb4cb910479d431683101",
+                    "external_id": "",
+                    "url": "https://api.github.com/repos/github/hello-world/check-runs/4",
+                    "html_url": "https://github.com/github/hello-world/runs/4",
+                    "details_url": "https://example.com",
+                    "status": "completed",
+                    "conclusion": "neutral",
+                    "started_at": "2018-05-04T01:14:52Z",
+                    "completed_at": "2018-05-04T01:14:52Z",
+                    "output": {
+                        "title": "Mighty Readme report",
+                        "summary": "There are 0 failures, 2 warnings, and 1 notice.",
+                        "annotations_count": 2
+                    },
+                    "check_suite": {"id": 5},
+                    "app": {"id": 1, "slug": "octoapp", "name": "Octocat App"},
+                    "pull_requests": []
+                }]
+            }"#,
+        )?;
+        assert_eq!(listing.total_count, 1);
+
+        let record: CheckRunRec = listing.items[0].clone().into();
+
+        assert_eq!(record.status, "completed".to_string());
+        assert_eq!(record.conclusion, Some("neutral".to_string()));
+        assert_eq!(
+            record.output_title,
+            Some("Mighty Readme report".to_string())
+        );
+        assert_eq!(record.annotations_count, Some(2));
+        assert_eq!(record.check_suite_id, Some(5));
+        assert_eq!(record.app_name, Some("Octocat App".to_string()));
+        assert!(record.completed_at.is_some());
+
+        Ok(())
+    }
+}
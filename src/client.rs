@@ -0,0 +1,589 @@
+use std::sync::{Arc, Mutex};
+use std::task::{Context, Poll};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+use bytes::Bytes;
+use chrono::{DateTime, Utc};
+use futures::future::BoxFuture;
+use http::header::{HeaderMap, HeaderValue, USER_AGENT};
+use http::{Request, Response, StatusCode, Uri};
+use http_body_util::{BodyExt, Either, Full};
+use log::*;
+use octocrab::auth::AppAuth;
+use octocrab::models::{AppId, InstallationId};
+use octocrab::service::middleware::{
+    auth_header::AuthHeaderLayer, base_uri::BaseUriLayer, extra_headers::ExtraHeadersLayer,
+};
+use octocrab::{AuthState, Octocrab, OctocrabBuilder};
+use rand::Rng;
+use tower::{Layer, Service, ServiceExt};
+
+// A request is sent at most this many times while it keeps being rate limited
+const RATE_LIMITED_ATTEMPTS: usize = 5;
+
+// How long to wait after a 429, or a 403 of a secondary rate limit, that says
+// nothing about when to come back, as recommended by GitHub
+const RATE_LIMITED_WAIT: Duration = Duration::from_secs(60);
+
+// Told only by the message of a 403 without the other headers
+const SECONDARY_RATE_LIMIT: &[u8] = b"secondary rate limit";
+
+pub enum Credentials {
+    Token(String),
+    App {
+        app_id: u64,
+        key: jsonwebtoken::EncodingKey,
+        installation_id: u64,
+    },
+}
+
+// When and how often a request that failed on the way is sent again: on a
+// 5xx response, a connection error or a timeout.
+#[derive(Debug, Clone)]
+pub struct RetryPolicy {
+    // Including the first one
+    pub max_attempts: usize,
+    // No retry is started after this long since the first attempt
+    pub max_elapsed: Duration,
+    // The backoff doubles from this on every retry, up to `max_backoff`, and
+    // a random part of it is waited
+    pub initial_backoff: Duration,
+    pub max_backoff: Duration,
+    // Until the response headers arrive
+    pub timeout: Duration,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        Self {
+            max_attempts: 5,
+            max_elapsed: Duration::from_secs(300),
+            initial_backoff: Duration::from_secs(1),
+            max_backoff: Duration::from_secs(60),
+            timeout: Duration::from_secs(120),
+        }
+    }
+}
+
+impl RetryPolicy {
+    // Doubles from `initial_backoff` on every retry, up to `max_backoff`
+    fn exponential_backoff(&self, retry: u32) -> Duration {
+        self.initial_backoff
+            .checked_mul(2u32.saturating_pow(retry))
+            .map_or(self.max_backoff, |backoff| backoff.min(self.max_backoff))
+    }
+
+    // "Full jitter": anything between zero and the exponential backoff, so
+    // that concurrent fetchers failing together do not retry together
+    fn backoff(&self, retry: u32) -> Duration {
+        self.exponential_backoff(retry)
+            .mul_f64(rand::thread_rng().gen_range(0.0..=1.0))
+    }
+}
+
+// Builds a client with the same middleware as `Octocrab::builder()`, except
+// that failed requests are retried as `retry` says, plus `limiter` keeping
+// requests within the rate limit. Clones of the client, e.g. the ones given
+// to fetchers, share the limiter.
+pub fn build(
+    base_uri: &str,
+    credentials: Credentials,
+    limiter: RateLimiter,
+    retry: RetryPolicy,
+) -> anyhow::Result<Octocrab> {
+    let base_uri: Uri = base_uri.parse()?;
+    let connector = hyper_rustls::HttpsConnectorBuilder::new()
+        .with_native_roots()?
+        .https_or_http()
+        .enable_http1()
+        .build();
+    let client = hyper_util::client::legacy::Client::builder(hyper_util::rt::TokioExecutor::new())
+        .build(connector);
+
+    let (auth_header, auth_state) = match &credentials {
+        Credentials::Token(token) => (
+            Some(HeaderValue::from_str(&format!("Bearer {}", token))?),
+            AuthState::None,
+        ),
+        Credentials::App { app_id, key, .. } => (
+            None,
+            AuthState::App(AppAuth {
+                app_id: AppId(*app_id),
+                key: key.clone(),
+            }),
+        ),
+    };
+    let headers = vec![(USER_AGENT, HeaderValue::from_static("octx"))];
+    let upload_uri = Uri::from_static("https://uploads.github.com");
+    let octocrab = OctocrabBuilder::new_empty()
+        .with_service(client)
+        .with_layer(&tower::timeout::TimeoutLayer::new(retry.timeout))
+        .with_layer(&RetryLayer(retry))
+        .with_layer(&tower_http::follow_redirect::FollowRedirectLayer::new())
+        .with_layer(&RateLimitLayer(limiter))
+        .with_layer(&ExtraHeadersLayer::new(Arc::new(headers)))
+        .with_layer(&BaseUriLayer::new(base_uri.clone()))
+        .with_layer(&AuthHeaderLayer::new(auth_header, base_uri, upload_uri))
+        .with_auth(auth_state)
+        .build()?;
+
+    match credentials {
+        Credentials::Token(_) => Ok(octocrab),
+        Credentials::App {
+            installation_id, ..
+        } => Ok(octocrab.installation(InstallationId(installation_id))?),
+    }
+}
+
+#[derive(Debug, Default)]
+struct Budget {
+    limit: Option<u64>,
+    remaining: Option<u64>,
+    reset: Option<SystemTime>,
+    // Until when a secondary rate limit asked to hold off
+    retry_at: Option<SystemTime>,
+}
+
+// The rate limit budget as told by the `X-RateLimit-*` headers of the last
+// response. Requests wait for the reset once the budget is used up, and for
+// `Retry-After` of a secondary rate limit.
+#[derive(Debug, Clone, Default)]
+pub struct RateLimiter(Arc<Mutex<Budget>>);
+
+fn header<T: std::str::FromStr>(headers: &HeaderMap, name: &str) -> Option<T> {
+    headers.get(name)?.to_str().ok()?.parse().ok()
+}
+
+impl RateLimiter {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn remaining(&self) -> Option<u64> {
+        self.0.lock().unwrap().remaining
+    }
+
+    // How long the next request has to wait, if at all
+    fn wait(&self) -> Option<Duration> {
+        let budget = self.0.lock().unwrap();
+        // The reset time is in seconds, so wait for the next second to be sure
+        let reset = match (budget.remaining, budget.reset) {
+            (Some(0), Some(reset)) => Some(reset + Duration::from_secs(1)),
+            _ => None,
+        };
+        let until = reset.max(budget.retry_at)?;
+        until.duration_since(SystemTime::now()).ok()
+    }
+
+    // Takes the budget from a response, and tells whether the request was
+    // rate limited and should be sent again after waiting. `secondary` is
+    // whether the message of a 403 says that a secondary rate limit was hit.
+    fn update(&self, status: StatusCode, headers: &HeaderMap, secondary: bool) -> bool {
+        let mut budget = self.0.lock().unwrap();
+        let now = SystemTime::now();
+        if let Some(remaining) = header::<u64>(headers, "x-ratelimit-remaining") {
+            let limit = header::<u64>(headers, "x-ratelimit-limit").or(budget.limit);
+            let reset = header::<u64>(headers, "x-ratelimit-reset")
+                .map(|reset| UNIX_EPOCH + Duration::from_secs(reset));
+            // Log every tenth of the limit used up, and warn about the last one
+            let tenth = limit.map_or(100, |limit| (limit / 10).max(1));
+            if budget.remaining.map(|r| r / tenth) != Some(remaining / tenth) {
+                let level = if remaining < tenth {
+                    Level::Warn
+                } else {
+                    Level::Info
+                };
+                log!(
+                    level,
+                    "Rate limit: {} of {} requests left until {}",
+                    remaining,
+                    limit.map_or("?".to_string(), |limit| limit.to_string()),
+                    reset.map_or("?".to_string(), |reset| DateTime::<Utc>::from(reset)
+                        .to_rfc3339()),
+                );
+            }
+            budget.limit = limit;
+            budget.remaining = Some(remaining);
+            budget.reset = reset;
+        }
+
+        if status != StatusCode::FORBIDDEN && status != StatusCode::TOO_MANY_REQUESTS {
+            return false;
+        }
+        if let Some(secs) = header::<u64>(headers, "retry-after") {
+            budget.retry_at = Some(now + Duration::from_secs(secs));
+            true
+        } else if budget.remaining == Some(0) {
+            true
+        } else if status == StatusCode::TOO_MANY_REQUESTS || secondary {
+            budget.retry_at = Some(now + RATE_LIMITED_WAIT);
+            true
+        } else {
+            // Any other 403 is a plain permission error
+            false
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct RateLimitLayer(pub RateLimiter);
+
+impl<S> Layer<S> for RateLimitLayer {
+    type Service = RateLimited<S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        RateLimited {
+            inner,
+            limiter: self.0.clone(),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct RateLimited<S> {
+    inner: S,
+    limiter: RateLimiter,
+}
+
+// `Request` cannot be cloned as a whole; the extensions are left out. The
+// body is shared, which is fine for the GET requests of the fetchers.
+fn clone_request<B: Clone>(request: &Request<B>) -> Request<B> {
+    let mut clone = Request::new(request.body().clone());
+    *clone.method_mut() = request.method().clone();
+    *clone.uri_mut() = request.uri().clone();
+    *clone.version_mut() = request.version();
+    *clone.headers_mut() = request.headers().clone();
+    clone
+}
+
+// Reads the body of a 403 to see whether it is about a secondary rate limit,
+// handing back a response with the body read
+async fn read_forbidden<B>(response: Response<B>) -> (Response<Either<B, Full<Bytes>>>, bool)
+where
+    B: http_body::Body<Data = Bytes>,
+{
+    if response.status() != StatusCode::FORBIDDEN {
+        return (response.map(Either::Left), false);
+    }
+    let (parts, body) = response.into_parts();
+    // A body that fails to be read is left empty, to fail on parsing it
+    let body = body
+        .collect()
+        .await
+        .map(|body| body.to_bytes())
+        .unwrap_or_default();
+    let secondary = body
+        .windows(SECONDARY_RATE_LIMIT.len())
+        .any(|window| window.eq_ignore_ascii_case(SECONDARY_RATE_LIMIT));
+    (
+        Response::from_parts(parts, Either::Right(Full::new(body))),
+        secondary,
+    )
+}
+
+impl<S, ReqB, B> Service<Request<ReqB>> for RateLimited<S>
+where
+    S: Service<Request<ReqB>, Response = Response<B>> + Clone + Send + 'static,
+    S::Future: Send,
+    S::Error: Send,
+    ReqB: Clone + Send + 'static,
+    B: http_body::Body<Data = Bytes> + Send + 'static,
+{
+    type Response = Response<Either<B, Full<Bytes>>>;
+    type Error = S::Error;
+    type Future = BoxFuture<'static, Result<Self::Response, S::Error>>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), S::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, request: Request<ReqB>) -> Self::Future {
+        // Take the service that was made ready, leaving a clone behind
+        let clone = self.inner.clone();
+        let mut inner = std::mem::replace(&mut self.inner, clone);
+        let limiter = self.limiter.clone();
+        Box::pin(async move {
+            let mut attempts = 0;
+            loop {
+                if let Some(wait) = limiter.wait() {
+                    warn!("Rate limit exceeded, waiting {}s", wait.as_secs() + 1);
+                    tokio::time::sleep(wait).await;
+                }
+                attempts += 1;
+                let response = inner.ready().await?.call(clone_request(&request)).await?;
+                let (response, secondary) = read_forbidden(response).await;
+                let limited = limiter.update(response.status(), response.headers(), secondary);
+                if !limited || attempts >= RATE_LIMITED_ATTEMPTS {
+                    return Ok(response);
+                }
+            }
+        })
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct RetryLayer(pub RetryPolicy);
+
+impl<S> Layer<S> for RetryLayer {
+    type Service = Retry<S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        Retry {
+            inner,
+            policy: self.0.clone(),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Retry<S> {
+    inner: S,
+    policy: RetryPolicy,
+}
+
+impl<S, ReqB, B> Service<Request<ReqB>> for Retry<S>
+where
+    S: Service<Request<ReqB>, Response = Response<B>> + Clone + Send + 'static,
+    S::Future: Send,
+    S::Error: std::fmt::Display + Send,
+    ReqB: Clone + Send + 'static,
+    B: Send + 'static,
+{
+    type Response = Response<B>;
+    type Error = S::Error;
+    type Future = BoxFuture<'static, Result<Response<B>, S::Error>>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), S::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, request: Request<ReqB>) -> Self::Future {
+        let clone = self.inner.clone();
+        let mut inner = std::mem::replace(&mut self.inner, clone);
+        let policy = self.policy.clone();
+        Box::pin(async move {
+            let started = Instant::now();
+            let mut attempts = 0;
+            loop {
+                attempts += 1;
+                let result = inner.ready().await?.call(clone_request(&request)).await;
+                let failure = match &result {
+                    Ok(response) if response.status().is_server_error() => {
+                        response.status().to_string()
+                    }
+                    Ok(_) => return result,
+                    Err(e) => e.to_string(),
+                };
+                let backoff = policy.backoff(attempts as u32 - 1);
+                if attempts >= policy.max_attempts
+                    || started.elapsed() + backoff > policy.max_elapsed
+                {
+                    return result;
+                }
+                warn!(
+                    "{} failed with {}, retrying in {:.1}s ({}/{})",
+                    request.uri(),
+                    failure,
+                    backoff.as_secs_f64(),
+                    attempts,
+                    policy.max_attempts
+                );
+                tokio::time::sleep(backoff).await;
+            }
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Instant;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use tokio::net::TcpListener;
+
+    enum Reply {
+        Send(String),
+        // Closes the connection without a response
+        Reset,
+        // Never responds
+        Hang,
+    }
+
+    // Replies to one connection after another with `replies`, and then closes
+    async fn mock_server(replies: Vec<Reply>) -> String {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move {
+            for reply in replies {
+                let (mut socket, _) = listener.accept().await.unwrap();
+                let mut buf = [0; 4096];
+                let _ = socket.read(&mut buf).await.unwrap();
+                match reply {
+                    Reply::Send(response) => {
+                        socket.write_all(response.as_bytes()).await.unwrap();
+                        socket.shutdown().await.unwrap();
+                    }
+                    Reply::Reset => drop(socket),
+                    Reply::Hang => {
+                        tokio::spawn(async move {
+                            tokio::time::sleep(Duration::from_secs(10)).await;
+                            drop(socket);
+                        });
+                    }
+                }
+            }
+        });
+        format!("http://{}/", addr)
+    }
+
+    fn response(status: &str, headers: &[(&str, String)]) -> Reply {
+        response_with_body(status, headers, "{}")
+    }
+
+    fn response_with_body(status: &str, headers: &[(&str, String)], body: &str) -> Reply {
+        let mut response = format!("HTTP/1.1 {}\r\nconnection: close\r\n", status);
+        for (name, value) in headers {
+            response.push_str(&format!("{}: {}\r\n", name, value));
+        }
+        response.push_str(&format!(
+            "content-type: application/json\r\ncontent-length: {}\r\n\r\n{}",
+            body.len(),
+            body
+        ));
+        Reply::Send(response)
+    }
+
+    fn quick_retries(max_attempts: usize) -> RetryPolicy {
+        RetryPolicy {
+            max_attempts,
+            initial_backoff: Duration::from_millis(10),
+            timeout: Duration::from_millis(500),
+            ..Default::default()
+        }
+    }
+
+    #[tokio::test]
+    async fn test_wait_for_rate_limit() -> Result<(), Box<dyn std::error::Error>> {
+        let reset = SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs() + 2;
+        let url = mock_server(vec![
+            response("403 Forbidden", &[("retry-after", "1".to_string())]),
+            response(
+                "200 OK",
+                &[
+                    ("x-ratelimit-limit", "5000".to_string()),
+                    ("x-ratelimit-remaining", "0".to_string()),
+                    ("x-ratelimit-reset", reset.to_string()),
+                ],
+            ),
+            response("200 OK", &[]),
+        ])
+        .await;
+
+        let limiter = RateLimiter::new();
+        let credentials = Credentials::Token("x".to_string());
+        let octocrab = build(&url, credentials, limiter.clone(), quick_retries(1))?;
+        let started = Instant::now();
+        // The first request is sent again after Retry-After
+        let _: serde_json::Value = octocrab.get("/first", None::<&()>).await?;
+        assert!(started.elapsed() >= Duration::from_secs(1));
+        assert_eq!(limiter.remaining(), Some(0));
+        // The second waits for the reset
+        let _: serde_json::Value = octocrab.get("/second", None::<&()>).await?;
+        assert!(SystemTime::now() >= UNIX_EPOCH + Duration::from_secs(reset));
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_wait_for_secondary_rate_limit() -> Result<(), Box<dyn std::error::Error>> {
+        let remaining = [("x-ratelimit-remaining", "4999".to_string())];
+        let url = mock_server(vec![
+            response_with_body(
+                "403 Forbidden",
+                &remaining,
+                r#"{"message":"You have exceeded a secondary rate limit."}"#,
+            ),
+            response_with_body(
+                "403 Forbidden",
+                &remaining,
+                r#"{"message":"Resource not accessible by integration"}"#,
+            ),
+        ])
+        .await;
+
+        let limiter = RateLimiter::new();
+        let credentials = Credentials::Token("x".to_string());
+        let octocrab = build(&url, credentials, limiter.clone(), quick_retries(1))?;
+        // Still waiting for a minute before sending the request again
+        let request = octocrab.get::<serde_json::Value, _, _>("/first", None::<&()>);
+        let result = tokio::time::timeout(Duration::from_secs(1), request).await;
+        assert!(result.is_err());
+        assert!(limiter.wait() > Some(Duration::from_secs(50)));
+
+        // A permission error fails at once
+        let limiter = RateLimiter::new();
+        let credentials = Credentials::Token("x".to_string());
+        let octocrab = build(&url, credentials, limiter.clone(), quick_retries(1))?;
+        let result: octocrab::Result<serde_json::Value> =
+            octocrab.get("/second", None::<&()>).await;
+        assert!(result.is_err());
+        assert_eq!(limiter.wait(), None);
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_retry_transient_failures() -> Result<(), Box<dyn std::error::Error>> {
+        let url = mock_server(vec![
+            response("502 Bad Gateway", &[]),
+            Reply::Reset,
+            Reply::Hang,
+            response("200 OK", &[]),
+        ])
+        .await;
+        let credentials = Credentials::Token("x".to_string());
+        let octocrab = build(&url, credentials, RateLimiter::new(), quick_retries(4))?;
+        let _: serde_json::Value = octocrab.get("/flaky", None::<&()>).await?;
+
+        let url = mock_server(vec![
+            response("503 Service Unavailable", &[]),
+            response("503 Service Unavailable", &[]),
+            response("200 OK", &[]),
+        ])
+        .await;
+        let credentials = Credentials::Token("x".to_string());
+        let octocrab = build(&url, credentials, RateLimiter::new(), quick_retries(2))?;
+        let result: octocrab::Result<serde_json::Value> = octocrab.get("/down", None::<&()>).await;
+        assert!(result.is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_backoff() {
+        let policy = RetryPolicy::default();
+        let secs = (0..8)
+            .map(|retry| policy.exponential_backoff(retry).as_secs())
+            .collect::<Vec<u64>>();
+        assert_eq!(secs, vec![1, 2, 4, 8, 16, 32, 60, 60]);
+        assert_eq!(policy.exponential_backoff(u32::MAX), policy.max_backoff);
+
+        for retry in 0..10 {
+            let backoffs = (0..100)
+                .map(|_| policy.backoff(retry))
+                .collect::<Vec<Duration>>();
+            assert!(backoffs
+                .iter()
+                .all(|backoff| *backoff <= policy.exponential_backoff(retry)));
+            // Jittered rather than the same every time
+            assert!(backoffs.iter().any(|backoff| *backoff != backoffs[0]));
+        }
+    }
+
+    #[test]
+    fn test_forbidden_without_rate_limit() {
+        let limiter = RateLimiter::new();
+        assert!(!limiter.update(StatusCode::FORBIDDEN, &HeaderMap::new(), false));
+        assert_eq!(limiter.wait(), None);
+    }
+}
@@ -2,24 +2,31 @@ use octocrab::models::issues::*;
 use url::Url;
 use serde::*;
 
+use async_stream::try_stream;
+use futures::{pin_mut, Stream, TryStreamExt};
+
+use crate::checkpoint::Cursor;
 use crate::*;
 
 type DateTime = chrono::DateTime<chrono::Utc>;
 
-#[derive(Serialize, Debug)]
-pub struct CommentRec {
-    pub id: u64,
-    pub node_id: String,
-    pub url: Url,
-    pub html_url: Url,
-    pub body: Option<String>,
-    pub body_text: Option<String>,
-    pub body_html: Option<String>,
-    pub user_id: i64,
-    pub created_at: DateTime,
-    pub updated_at: Option<DateTime>,
-
-    pub sdc_repository: String,
+record! {
+    #[table(name = "comments", watermark = updated_at)]
+    #[derive(Serialize, Debug)]
+    pub struct CommentRec {
+        pub id: u64,
+        pub node_id: String,
+        pub url: Url,
+        pub html_url: Url,
+        pub body: Option<String>,
+        pub body_text: Option<String>,
+        pub body_html: Option<String>,
+        pub user_id: i64,
+        pub created_at: DateTime,
+        pub updated_at: Option<DateTime>,
+
+        pub sdc_repository: String,
+    }
 }
 
 impl RepositryAware for CommentRec {
@@ -52,6 +59,7 @@ pub struct CommentFetcher {
     name: String,
     since: Option<DateTime>,
     octocrab: octocrab::Octocrab,
+    cursor: Cursor,
 }
 
 impl CommentFetcher {
@@ -66,8 +74,14 @@ impl CommentFetcher {
             name,
             since,
             octocrab,
+            cursor: Cursor::default(),
         }
     }
+
+    pub fn with_cursor(mut self, cursor: Cursor) -> Self {
+        self.cursor = cursor;
+        self
+    }
 }
 
 impl UrlConstructor for CommentFetcher {
@@ -75,18 +89,18 @@ impl UrlConstructor for CommentFetcher {
         format!("{}/{}", self.owner, self.name)
     }
 
-    fn entrypoint_route(&self) -> String {
+    fn entrypoint_route(&self) -> crate::Result<String> {
         let param = Params {
             since: self.since,
             ..Default::default()
         };
 
-        format!(
+        Ok(format!(
             "/repos/{owner}/{repo}/issues/comments?{query}",
             owner = &self.owner,
             repo = &self.name,
-            query = param.to_query(),
-        )
+            query = param.to_query()?,
+        ))
     }
 }
 
@@ -96,17 +110,22 @@ impl LoopWriter for CommentFetcher {
 }
 
 impl CommentFetcher {
-    pub async fn fetch<T: std::io::Write>(&self, mut wtr: csv::Writer<T>) -> octocrab::Result<()> {
-        let first: octocrab::Page<Comment> = self
-            .octocrab
-            .get(self.entrypoint_route(), None::<&()>)
-            .await?;
-        let mut next = self.write_and_continue(first, &mut wtr);
-
-        while let Some(page) = self.octocrab.get_page(&next).await? {
-            next = self.write_and_continue(page, &mut wtr);
+    pub fn stream(&self) -> impl Stream<Item = crate::Result<CommentRec>> + '_ {
+        try_stream! {
+            let pages = self.cursor.pages::<Comment>(&self.octocrab, self.entrypoint_route()?);
+            pin_mut!(pages);
+            while let Some(mut page) = pages.try_next().await? {
+                for record in self.take_records(&mut page) {
+                    yield record;
+                }
+            }
         }
+    }
 
-        Ok(())
+    pub async fn fetch<S: RecordSink<CommentRec> + ?Sized>(
+        &self,
+        sink: &mut S,
+    ) -> crate::Result<()> {
+        write_stream(self.stream(), sink).await
     }
 }
@@ -1,6 +1,13 @@
 use url::Url;
 use serde::*;
 
+use std::future::Future;
+
+use async_stream::try_stream;
+use futures::{pin_mut, stream, Stream, StreamExt, TryFutureExt, TryStreamExt};
+
+use crate::checkpoint::Cursor;
+use crate::error::skip_failed;
 use crate::*;
 
 type DateTime = chrono::DateTime<chrono::Utc>;
@@ -57,22 +64,25 @@ pub struct GitUser {
     pub date: DateTime, // is required
 }
 
-#[derive(Serialize, Debug)]
-pub struct CommitRec {
-    pub sha: Option<String>,
-    pub node_id: Option<String>,
-    pub url: Option<String>,
-    pub html_url: Option<String>,
-    pub comments_url: Option<String>,
-    pub author_id: Option<i64>,
-    pub committer_id: Option<i64>,
-    pub parents: String, // Vec.to_json
-    pub message: Option<String>,
-    pub authorized_at: Option<DateTime>,
-    pub committed_at: Option<DateTime>,
-    pub comment_count: i32,
+record! {
+    #[table(name = "commits", primary_key = ["sha"], watermark = committed_at)]
+    #[derive(Serialize, Debug)]
+    pub struct CommitRec {
+        pub sha: Option<String>,
+        pub node_id: Option<String>,
+        pub url: Option<String>,
+        pub html_url: Option<String>,
+        pub comments_url: Option<String>,
+        pub author_id: Option<i64>,
+        pub committer_id: Option<i64>,
+        pub parents: String, // Vec.to_json
+        pub message: Option<String>,
+        pub authorized_at: Option<DateTime>,
+        pub committed_at: Option<DateTime>,
+        pub comment_count: i32,
 
-    pub sdc_repository: String,
+        pub sdc_repository: String,
+    }
 }
 
 impl RepositryAware for CommitRec {
@@ -89,8 +99,8 @@ impl From<Commit> for CommitRec {
             url: from.url,
             html_url: from.html_url,
             comments_url: from.comments_url,
-            author_id: from.author.map(|u| u.id).flatten(),
-            committer_id: from.committer.map(|u| u.id).flatten(),
+            author_id: from.author.and_then(|u| u.id),
+            committer_id: from.committer.and_then(|u| u.id),
             parents: from
                 .parents
                 .iter()
@@ -112,6 +122,7 @@ pub struct CommitFetcher {
     name: String,
     since: Option<DateTime>,
     octocrab: octocrab::Octocrab,
+    cursor: Cursor,
 }
 
 impl CommitFetcher {
@@ -126,8 +137,14 @@ impl CommitFetcher {
             name,
             since,
             octocrab,
+            cursor: Cursor::default(),
         }
     }
+
+    pub fn with_cursor(mut self, cursor: Cursor) -> Self {
+        self.cursor = cursor;
+        self
+    }
 }
 
 impl UrlConstructor for CommitFetcher {
@@ -135,18 +152,18 @@ impl UrlConstructor for CommitFetcher {
         format!("{}/{}", self.owner, self.name)
     }
 
-    fn entrypoint_route(&self) -> String {
+    fn entrypoint_route(&self) -> crate::Result<String> {
         let param = Params {
             since: self.since,
             ..Default::default()
         };
 
-        format!(
+        Ok(format!(
             "/repos/{owner}/{repo}/commits?{query}",
             owner = &self.owner,
             repo = &self.name,
-            query = param.to_query(),
-        )
+            query = param.to_query()?,
+        ))
     }
 }
 
@@ -156,17 +173,109 @@ impl LoopWriter for CommitFetcher {
 }
 
 impl CommitFetcher {
-    pub async fn fetch<T: std::io::Write>(&self, mut wtr: csv::Writer<T>) -> octocrab::Result<()> {
-        let first: octocrab::Page<Commit> = self
-            .octocrab
-            .get(self.entrypoint_route(), None::<&()>)
-            .await?;
-        let mut next = self.write_and_continue(first, &mut wtr);
-
-        while let Some(page) = self.octocrab.get_page(&next).await? {
-            next = self.write_and_continue(page, &mut wtr);
+    pub fn stream(&self) -> impl Stream<Item = crate::Result<CommitRec>> + '_ {
+        try_stream! {
+            let pages = self.cursor.pages::<Commit>(&self.octocrab, self.entrypoint_route()?);
+            pin_mut!(pages);
+            while let Some(mut page) = pages.try_next().await? {
+                for record in self.take_records(&mut page) {
+                    yield record;
+                }
+            }
         }
+    }
+
+    pub async fn fetch<S: RecordSink<CommitRec> + ?Sized>(
+        &self,
+        sink: &mut S,
+    ) -> crate::Result<()> {
+        write_stream(self.stream(), sink).await
+    }
+}
+
+// Walks the commits of the default branch since `since` for fetchers of what
+// belongs to each commit, such as its checks or statuses, fetching that of
+// `concurrency` commits at a time. The cursor follows the commit listing.
+pub struct CommitWalker {
+    owner: String,
+    name: String,
+    since: Option<DateTime>,
+    octocrab: octocrab::Octocrab,
+    cursor: Cursor,
+    concurrency: usize,
+    skip_errors: bool,
+}
 
-        Ok(())
+impl CommitWalker {
+    pub fn new(
+        owner: String,
+        name: String,
+        since: Option<DateTime>,
+        octocrab: octocrab::Octocrab,
+    ) -> Self {
+        Self {
+            owner,
+            name,
+            since,
+            octocrab,
+            cursor: Cursor::default(),
+            concurrency: PULL_CONCURRENCY,
+            skip_errors: false,
+        }
+    }
+
+    pub fn with_cursor(mut self, cursor: Cursor) -> Self {
+        self.cursor = cursor;
+        self
+    }
+
+    pub fn with_concurrency(mut self, concurrency: usize) -> Self {
+        self.concurrency = concurrency.max(1);
+        self
+    }
+
+    pub fn with_skip_errors(mut self, yes: bool) -> Self {
+        self.skip_errors = yes;
+        self
+    }
+
+    // Yields what `fetch` returns for the sha of each commit; `what` names it
+    // in errors, e.g. "statuses"
+    pub fn walk<'a, T, F, Fut>(
+        &'a self,
+        what: &'a str,
+        fetch: F,
+    ) -> impl Stream<Item = crate::Result<T>> + 'a
+    where
+        T: 'a,
+        F: Fn(String) -> Fut + 'a,
+        Fut: Future<Output = crate::Result<Vec<T>>> + 'a,
+    {
+        try_stream! {
+            let commit_fetcher = CommitFetcher::new(
+                self.owner.clone(),
+                self.name.clone(),
+                self.since,
+                self.octocrab.clone(),
+            );
+            let route = commit_fetcher.entrypoint_route()?;
+            let commits = self.cursor.pages::<Commit>(&self.octocrab, route);
+            pin_mut!(commits);
+            while let Some(mut page) = commits.try_next().await? {
+                let commits: Vec<Commit> = page.take_items();
+                let children = stream::iter(commits.into_iter().filter_map(|commit| commit.sha))
+                    .map(|sha| {
+                        let item = format!("{} of commit {}", what, sha);
+                        fetch(sha).map_err(|e| Error::item(item, e))
+                    })
+                    .buffered(self.concurrency);
+                pin_mut!(children);
+                while let Some(children) = children.next().await {
+                    for child in skip_failed(children, self.skip_errors)? {
+                        yield child;
+                    }
+                }
+            }
+        }
     }
 }
@@ -0,0 +1,301 @@
+use std::collections::HashSet;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use anyhow::{bail, Context};
+use serde::Deserialize;
+
+use crate::client::RetryPolicy;
+use crate::repos::VISIBILITIES;
+use crate::OutputFormat;
+
+// A batch of extractions described in a TOML file, for `octx run --config`.
+// Anything left out of `api_url` and `[auth]` is read from the environment
+// as for a one-off run.
+#[derive(Deserialize, Debug, Default, PartialEq)]
+#[serde(deny_unknown_fields)]
+pub struct Config {
+    pub api_url: Option<String>,
+    #[serde(default)]
+    pub auth: Auth,
+    // Same as --state-dir
+    pub state_dir: Option<PathBuf>,
+    // Same as --concurrency
+    pub concurrency: Option<usize>,
+    // Same as --skip-errors
+    #[serde(default)]
+    pub skip_errors: bool,
+    #[serde(default)]
+    pub retry: RetryConfig,
+    #[serde(default)]
+    pub output: OutputConfig,
+    #[serde(default)]
+    pub jobs: Vec<Job>,
+}
+
+// Either a personal access token, given as is or as the name of an
+// environment variable holding it, or a GitHub App installation.
+#[derive(Deserialize, Debug, Default, PartialEq)]
+#[serde(deny_unknown_fields)]
+pub struct Auth {
+    pub token: Option<String>,
+    pub token_env: Option<String>,
+    pub app_id: Option<u64>,
+    pub private_key_path: Option<String>,
+    pub installation_id: Option<u64>,
+}
+
+impl Auth {
+    pub fn is_empty(&self) -> bool {
+        self == &Auth::default()
+    }
+
+    pub fn token(&self) -> anyhow::Result<Option<String>> {
+        match (&self.token, &self.token_env) {
+            (Some(_), Some(_)) => bail!("set either token or token_env in [auth]"),
+            (Some(token), None) => Ok(Some(token.clone())),
+            (None, Some(name)) => Ok(Some(
+                std::env::var(name).with_context(|| format!("while reading {}", name))?,
+            )),
+            (None, None) => Ok(None),
+        }
+    }
+}
+
+// Same as --max-attempts and --max-retry-secs
+#[derive(Deserialize, Debug, Default, PartialEq)]
+#[serde(deny_unknown_fields)]
+pub struct RetryConfig {
+    pub max_attempts: Option<usize>,
+    pub max_elapsed_secs: Option<u64>,
+}
+
+impl RetryConfig {
+    pub fn policy(&self) -> RetryPolicy {
+        let default = RetryPolicy::default();
+        RetryPolicy {
+            max_attempts: self.max_attempts.unwrap_or(default.max_attempts),
+            max_elapsed: self
+                .max_elapsed_secs
+                .map_or(default.max_elapsed, Duration::from_secs),
+            ..default
+        }
+    }
+}
+
+#[derive(Deserialize, Debug, Default, PartialEq)]
+#[serde(deny_unknown_fields)]
+pub struct OutputConfig {
+    #[serde(default)]
+    pub format: OutputFormat,
+    pub out_dir: Option<PathBuf>,
+    pub sqlite: Option<PathBuf>,
+    pub postgres: Option<String>,
+}
+
+// Targets to extract from a list of repositories or from an organization.
+#[derive(Deserialize, Debug, Default, PartialEq)]
+#[serde(deny_unknown_fields)]
+pub struct Job {
+    // "owner/name"
+    #[serde(default)]
+    pub repositories: Vec<String>,
+    pub org: Option<String>,
+    #[serde(default)]
+    pub skip_archived: bool,
+    #[serde(default)]
+    pub skip_forks: bool,
+    pub visibility: Option<String>,
+    // Named as the options without dashes, e.g. "pull-request-files"
+    pub targets: Vec<String>,
+    pub days_ago: Option<i64>,
+    pub since_date: Option<String>,
+    // Overrides `out_dir` of `[output]`
+    pub out_dir: Option<PathBuf>,
+}
+
+impl Job {
+    pub fn out_dir<'a>(&'a self, output: &'a OutputConfig) -> Option<&'a Path> {
+        self.out_dir.as_deref().or(output.out_dir.as_deref())
+    }
+}
+
+impl Config {
+    pub fn load(path: impl AsRef<Path>) -> anyhow::Result<Self> {
+        let config: Config = toml::from_str(&fs::read_to_string(path)?)?;
+        config.validate()?;
+        Ok(config)
+    }
+
+    fn validate(&self) -> anyhow::Result<()> {
+        if self.concurrency == Some(0) {
+            bail!("concurrency must be at least 1");
+        }
+        if self.retry.max_attempts == Some(0) {
+            bail!("max_attempts must be at least 1 in [retry]");
+        }
+        // Two jobs writing a target to the same directory would overwrite
+        // each other's file
+        let mut files = HashSet::new();
+        // Likewise, only one target can be written to stdout
+        let to_database = self.output.sqlite.is_some() || self.output.postgres.is_some();
+        let mut to_stdout = 0;
+        for (i, job) in self.jobs.iter().enumerate() {
+            if job.repositories.is_empty() == job.org.is_none() {
+                bail!("job {}: set either repositories or org", i + 1);
+            }
+            if job.targets.is_empty() {
+                bail!("job {}: no targets", i + 1);
+            }
+            if let Some(visibility) = &job.visibility {
+                if !VISIBILITIES.contains(&visibility.as_str()) {
+                    bail!(
+                        "job {}: visibility must be one of {}",
+                        i + 1,
+                        VISIBILITIES.join(", ")
+                    );
+                }
+            }
+            if let Some(dir) = job.out_dir(&self.output) {
+                for target in job.targets.iter() {
+                    if !files.insert((dir, target)) {
+                        bail!(
+                            "job {}: {} is already written to {}; give the job its own out_dir",
+                            i + 1,
+                            target,
+                            dir.display()
+                        );
+                    }
+                }
+            } else if !to_database {
+                to_stdout += job.targets.len();
+                if to_stdout > 1 {
+                    bail!(
+                        "job {}: more than one target would be written to stdout; set out_dir",
+                        i + 1
+                    );
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_config() -> Result<(), Box<dyn std::error::Error>> {
+        let config: Config = toml::from_str(
+            r#"
+            api_url = "https://github.example.com/api/v3/"
+            state_dir = "./state"
+            concurrency = 4
+            skip_errors = true
+
+            [auth]
+            token_env = "OCTX_TOKEN"
+
+            [retry]
+            max_attempts = 3
+
+            [output]
+            format = "jsonl"
+            out_dir = "./dump"
+
+            [[jobs]]
+            repositories = ["octocat/Hello-World"]
+            targets = ["issues", "comments"]
+            days_ago = 7
+
+            [[jobs]]
+            org = "acme"
+            skip_archived = true
+            targets = ["issues"]
+            out_dir = "./dump/acme"
+            "#,
+        )?;
+        config.validate()?;
+
+        assert_eq!(config.concurrency, Some(4));
+        assert!(config.skip_errors);
+        assert_eq!(config.retry.policy().max_attempts, 3);
+        assert_eq!(config.retry.policy().max_elapsed, Duration::from_secs(300));
+        assert_eq!(config.output.format, OutputFormat::Jsonl);
+        assert_eq!(config.auth.token_env.as_deref(), Some("OCTX_TOKEN"));
+        assert_eq!(config.jobs.len(), 2);
+        assert_eq!(config.jobs[0].days_ago, Some(7));
+        assert_eq!(
+            config.jobs[0].out_dir(&config.output),
+            Some(Path::new("./dump"))
+        );
+        assert_eq!(config.jobs[1].org.as_deref(), Some("acme"));
+        assert_eq!(
+            config.jobs[1].out_dir(&config.output),
+            Some(Path::new("./dump/acme"))
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_reject_overwriting_jobs() -> Result<(), Box<dyn std::error::Error>> {
+        let config: Config = toml::from_str(
+            r#"
+            [output]
+            out_dir = "./dump"
+
+            [[jobs]]
+            repositories = ["octocat/Hello-World"]
+            targets = ["issues"]
+
+            [[jobs]]
+            org = "acme"
+            targets = ["issues"]
+            "#,
+        )?;
+        assert!(config.validate().is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_reject_targets_to_stdout() -> Result<(), Box<dyn std::error::Error>> {
+        let config: Config = toml::from_str(
+            r#"
+            [[jobs]]
+            repositories = ["octocat/Hello-World"]
+            targets = ["issues", "comments"]
+            "#,
+        )?;
+        assert!(config.validate().is_err());
+
+        let config: Config = toml::from_str(
+            r#"
+            [output]
+            sqlite = "octx.db"
+
+            [[jobs]]
+            repositories = ["octocat/Hello-World"]
+            targets = ["issues", "comments"]
+            "#,
+        )?;
+        config.validate()?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_reject_unknown_visibility() -> Result<(), Box<dyn std::error::Error>> {
+        let config: Config = toml::from_str(
+            r#"
+            [[jobs]]
+            org = "acme"
+            visibility = "secret"
+            targets = ["issues"]
+            "#,
+        )?;
+        assert!(config.validate().is_err());
+        Ok(())
+    }
+}
@@ -0,0 +1,440 @@
+use async_stream::try_stream;
+use futures::{pin_mut, stream, Stream, StreamExt, TryFutureExt, TryStreamExt};
+use octocrab::models::Author;
+use serde::*;
+use url::Url;
+
+use crate::checkpoint::Cursor;
+use crate::error::skip_failed;
+use crate::*;
+
+type DateTime = chrono::DateTime<chrono::Utc>;
+
+// Leaving out payload, which is either a string or an object
+// ref: https://docs.github.com/en/rest/deployments/deployments
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[non_exhaustive]
+pub struct Deployment {
+    pub id: i64,
+    pub node_id: String,
+    pub url: Url,
+    pub sha: String,
+    #[serde(rename = "ref")]
+    pub git_ref: String,
+    pub task: String,
+    pub environment: String,
+    #[serde(default)]
+    pub original_environment: Option<String>,
+    pub description: Option<String>,
+    pub creator: Option<Author>,
+    #[serde(default)]
+    pub transient_environment: Option<bool>,
+    #[serde(default)]
+    pub production_environment: Option<bool>,
+    pub created_at: DateTime,
+    pub updated_at: DateTime,
+}
+
+// ref: https://docs.github.com/en/rest/deployments/statuses
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[non_exhaustive]
+pub struct DeploymentStatus {
+    pub id: i64,
+    pub node_id: String,
+    pub url: Url,
+    pub state: String,
+    pub creator: Option<Author>,
+    pub description: Option<String>,
+    #[serde(default)]
+    pub environment: Option<String>,
+    pub target_url: Option<String>,
+    #[serde(default)]
+    pub environment_url: Option<String>,
+    #[serde(default)]
+    pub log_url: Option<String>,
+    pub created_at: DateTime,
+    pub updated_at: DateTime,
+}
+
+record! {
+    #[table(name = "deployments", watermark = created_at)]
+    #[derive(Serialize, Debug)]
+    pub struct DeploymentRec {
+        pub id: i64,
+        pub node_id: String,
+        pub url: Url,
+        pub sha: String,
+        pub git_ref: String,
+        pub task: String,
+        pub environment: String,
+        pub original_environment: Option<String>,
+        pub description: Option<String>,
+        pub creator_id: Option<i64>,
+        pub transient_environment: Option<bool>,
+        pub production_environment: Option<bool>,
+        pub created_at: DateTime,
+        pub updated_at: DateTime,
+
+        pub sdc_repository: String,
+    }
+}
+
+impl RepositryAware for DeploymentRec {
+    fn set_repository(&mut self, name: String) {
+        self.sdc_repository = name;
+    }
+}
+
+impl From<Deployment> for DeploymentRec {
+    fn from(from: Deployment) -> Self {
+        Self {
+            id: from.id,
+            node_id: from.node_id,
+            url: from.url,
+            sha: from.sha,
+            git_ref: from.git_ref,
+            task: from.task,
+            environment: from.environment,
+            original_environment: from.original_environment,
+            description: from.description,
+            creator_id: from.creator.map(|u| u.id.0 as i64),
+            transient_environment: from.transient_environment,
+            production_environment: from.production_environment,
+            created_at: from.created_at,
+            updated_at: from.updated_at,
+
+            sdc_repository: String::default(),
+        }
+    }
+}
+
+record! {
+    #[table(name = "deployment_statuses", watermark = deployment_created_at)]
+    #[derive(Serialize, Debug)]
+    pub struct DeploymentStatusRec {
+        pub id: i64,
+        pub node_id: String,
+        pub url: Url,
+        pub deployment_id: i64,
+        // Deployments are walked by when they were created, so that is the
+        // watermark rather than when the status was
+        pub deployment_created_at: DateTime,
+        pub state: String,
+        pub description: Option<String>,
+        pub environment: Option<String>,
+        pub target_url: Option<String>,
+        pub environment_url: Option<String>,
+        pub log_url: Option<String>,
+        pub creator_id: Option<i64>,
+        pub created_at: DateTime,
+        pub updated_at: DateTime,
+
+        pub sdc_repository: String,
+    }
+}
+
+impl RepositryAware for DeploymentStatusRec {
+    fn set_repository(&mut self, name: String) {
+        self.sdc_repository = name;
+    }
+}
+
+impl From<DeploymentStatus> for DeploymentStatusRec {
+    fn from(from: DeploymentStatus) -> Self {
+        Self {
+            id: from.id,
+            node_id: from.node_id,
+            url: from.url,
+            deployment_id: 0,
+            deployment_created_at: DateTime::default(),
+            state: from.state,
+            description: from.description,
+            environment: from.environment,
+            target_url: from.target_url,
+            environment_url: from.environment_url,
+            log_url: from.log_url,
+            creator_id: from.creator.map(|u| u.id.0 as i64),
+            created_at: from.created_at,
+            updated_at: from.updated_at,
+
+            sdc_repository: String::default(),
+        }
+    }
+}
+
+pub struct DeploymentFetcher {
+    owner: String,
+    name: String,
+    since: Option<DateTime>,
+    octocrab: octocrab::Octocrab,
+    cursor: Cursor,
+}
+
+// Walks the deployments created since `since`, `concurrency` deployments at a
+// time, so a status added later to an older deployment is not seen
+pub struct DeploymentStatusFetcher {
+    owner: String,
+    name: String,
+    since: Option<DateTime>,
+    octocrab: octocrab::Octocrab,
+    cursor: Cursor,
+    concurrency: usize,
+    skip_errors: bool,
+}
+
+impl DeploymentFetcher {
+    pub fn new(
+        owner: String,
+        name: String,
+        since: Option<DateTime>,
+        octocrab: octocrab::Octocrab,
+    ) -> Self {
+        Self {
+            owner,
+            name,
+            since,
+            octocrab,
+            cursor: Cursor::default(),
+        }
+    }
+
+    pub fn with_cursor(mut self, cursor: Cursor) -> Self {
+        self.cursor = cursor;
+        self
+    }
+}
+
+impl UrlConstructor for DeploymentFetcher {
+    fn reponame(&self) -> String {
+        format!("{}/{}", self.owner, self.name)
+    }
+
+    // Deployments are listed newest first and take no since
+    fn entrypoint_route(&self) -> crate::Result<String> {
+        let param = Params::default();
+
+        Ok(format!(
+            "/repos/{owner}/{repo}/deployments?{query}",
+            owner = &self.owner,
+            repo = &self.name,
+            query = param.to_query()?,
+        ))
+    }
+}
+
+impl LoopWriter for DeploymentFetcher {
+    type Model = Deployment;
+    type Record = DeploymentRec;
+}
+
+impl DeploymentFetcher {
+    pub fn stream(&self) -> impl Stream<Item = crate::Result<DeploymentRec>> + '_ {
+        try_stream! {
+            let route = self.entrypoint_route()?;
+            let pages = self.cursor.pages::<Deployment>(&self.octocrab, route);
+            pin_mut!(pages);
+            while let Some(mut page) = pages.try_next().await? {
+                let mut last_created: Option<DateTime> = None;
+                for record in self.take_records(&mut page) {
+                    last_created = Some(record.created_at);
+                    yield record;
+                }
+
+                if let Some(since) = self.since {
+                    if last_created.is_none_or(|last| last < since) {
+                        break;
+                    }
+                }
+            }
+        }
+    }
+
+    pub async fn fetch<S: RecordSink<DeploymentRec> + ?Sized>(
+        &self,
+        sink: &mut S,
+    ) -> crate::Result<()> {
+        write_stream(self.stream(), sink).await
+    }
+}
+
+impl DeploymentStatusFetcher {
+    pub fn new(
+        owner: String,
+        name: String,
+        since: Option<DateTime>,
+        octocrab: octocrab::Octocrab,
+    ) -> Self {
+        Self {
+            owner,
+            name,
+            since,
+            octocrab,
+            cursor: Cursor::default(),
+            concurrency: PULL_CONCURRENCY,
+            skip_errors: false,
+        }
+    }
+
+    pub fn with_cursor(mut self, cursor: Cursor) -> Self {
+        self.cursor = cursor;
+        self
+    }
+
+    // Deployments of a page whose statuses are fetched at a time
+    pub fn with_concurrency(mut self, concurrency: usize) -> Self {
+        self.concurrency = concurrency.max(1);
+        self
+    }
+
+    // Log and leave out a deployment whose statuses cannot be fetched instead
+    // of failing
+    pub fn with_skip_errors(mut self, yes: bool) -> Self {
+        self.skip_errors = yes;
+        self
+    }
+
+    fn reponame(&self) -> String {
+        format!("{}/{}", self.owner, self.name)
+    }
+
+    fn entrypoint_route(&self, deployment_id: i64) -> crate::Result<String> {
+        let param = Params::default();
+
+        Ok(format!(
+            "/repos/{owner}/{repo}/deployments/{deployment_id}/statuses?{query}",
+            owner = &self.owner,
+            repo = &self.name,
+            deployment_id = deployment_id,
+            query = param.to_query()?,
+        ))
+    }
+
+    async fn deployment_statuses(
+        &self,
+        deployment: &Deployment,
+    ) -> crate::Result<Vec<DeploymentStatusRec>> {
+        let route = self.entrypoint_route(deployment.id)?;
+        let pages = pages::<DeploymentStatus>(&self.octocrab, route);
+        pin_mut!(pages);
+        let mut statuses = vec![];
+        while let Some(mut page) = pages.try_next().await? {
+            for status in page.take_items() {
+                let mut status: DeploymentStatusRec = status.into();
+                status.deployment_id = deployment.id;
+                status.deployment_created_at = deployment.created_at;
+                status.set_repository(self.reponame());
+                statuses.push(status);
+            }
+        }
+        Ok(statuses)
+    }
+
+    pub fn stream(&self) -> impl Stream<Item = crate::Result<DeploymentStatusRec>> + '_ {
+        try_stream! {
+            let deployment_fetcher = DeploymentFetcher::new(
+                self.owner.clone(),
+                self.name.clone(),
+                self.since,
+                self.octocrab.clone(),
+            );
+            let route = deployment_fetcher.entrypoint_route()?;
+            let deployments = self.cursor.pages::<Deployment>(&self.octocrab, route);
+            pin_mut!(deployments);
+            while let Some(mut page) = deployments.try_next().await? {
+                let deployments: Vec<Deployment> = page.take_items();
+                let last_created = deployments.last().map(|d| d.created_at);
+                // The same cut-off as the watermark, for the older ones of the
+                // last page
+                let recent = deployments
+                    .iter()
+                    .filter(|d| self.since.is_none_or(|since| d.created_at >= since));
+                let statuses = stream::iter(recent)
+                    .map(|deployment| {
+                        let item = format!("statuses of deployment {}", deployment.id);
+                        self.deployment_statuses(deployment)
+                            .map_err(|e| Error::item(item, e))
+                    })
+                    .buffered(self.concurrency);
+                pin_mut!(statuses);
+                while let Some(statuses) = statuses.next().await {
+                    for status in skip_failed(statuses, self.skip_errors)? {
+                        yield status;
+                    }
+                }
+
+                if let Some(since) = self.since {
+                    if last_created.is_none_or(|last| last < since) {
+                        break;
+                    }
+                }
+            }
+        }
+    }
+
+    pub async fn fetch<S: RecordSink<DeploymentStatusRec> + ?Sized>(
+        &self,
+        sink: &mut S,
+    ) -> crate::Result<()> {
+        write_stream(self.stream(), sink).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::schema::Table;
+
+    #[test]
+    fn test_convert_deployment_models() -> Result<(), Box<dyn std::error::Error>> {
+        let deployment: Deployment = serde_json::from_str(
+            r#"{
+                "url": "https://api.github.com/repos/octocat/example/deployments/1",
+                "id": 1,
+                "node_id": "MDEwOkRlcGxveW1lbnQx",
+                "sha": "a84d88e7554fc1fa21bcbc4efae3c782a70d2b9d",
+                "ref": "topic-branch",
+                "task": "deploy",
+                "payload": {},
+                "environment": "production",
+                "description": "Deploy request from hubot",
+                "creator": null,
+                "created_at": "2012-07-20T01:19:13Z",
+                "updated_at": "2012-07-20T01:19:13Z"
+            }"#,
+        )?;
+        let status: DeploymentStatus = serde_json::from_str(
+            r#"{
+                "url": "https://api.github.com/repos/octocat/example/deployments/42/statuses/1",
+                "id": 1,
+                "node_id": "MDE2OkRlcGxveW1lbnRTdGF0dXMx",
+                "state": "success",
+                "creator": null,
+                "description": "Deployment finished successfully.",
+                "environment": "production",
+                "target_url": "https://example.com/deployment/42/output",
+                "created_at": "2012-07-20T01:19:13Z",
+                "updated_at": "2012-07-20T01:19:13Z",
+                "environment_url": "https://test-branch.lab.acme.com",
+                "log_url": "https://example.com/deployment/42/output"
+            }"#,
+        )?;
+
+        let mut status: DeploymentStatusRec = status.into();
+        status.deployment_created_at = "2012-07-19T00:00:00Z".parse()?;
+        // Walked and watermarked by when the deployment was created
+        assert_eq!(status.watermark(), Some(status.deployment_created_at));
+        let deployment: DeploymentRec = deployment.into();
+
+        assert_eq!(deployment.git_ref, "topic-branch".to_string());
+        assert_eq!(deployment.environment, "production".to_string());
+        assert_eq!(deployment.creator_id, None);
+        assert_eq!(deployment.production_environment, None);
+        assert_eq!(status.state, "success".to_string());
+        assert_eq!(
+            status.log_url.as_deref(),
+            Some("https://example.com/deployment/42/output")
+        );
+
+        Ok(())
+    }
+}
@@ -0,0 +1,106 @@
+use std::fmt;
+
+use log::*;
+
+pub type Result<T, E = Error> = std::result::Result<T, E>;
+
+#[derive(Debug)]
+#[non_exhaustive]
+pub enum Error {
+    // A request to the GitHub API failed
+    GitHub(octocrab::Error),
+    // Fetching something that belongs to one record of a listing failed,
+    // e.g. the files of a pull request or the jobs of a workflow run
+    Item { item: String, source: Box<Error> },
+    // Query parameters of a route could not be encoded
+    Query(serde_urlencoded::ser::Error),
+    // A sink failed to take a record
+    Sink(anyhow::Error),
+}
+
+impl Error {
+    pub(crate) fn item(item: String, source: impl Into<Error>) -> Error {
+        Error::Item {
+            item,
+            source: Box::new(source.into()),
+        }
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::GitHub(e) => e.fmt(f),
+            Error::Item { item, .. } => write!(f, "failed to fetch {}", item),
+            Error::Query(_) => write!(f, "failed to encode query parameters"),
+            Error::Sink(_) => write!(f, "failed to write a record"),
+        }
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Error::GitHub(e) => e.source(),
+            Error::Item { source, .. } => Some(source.as_ref()),
+            Error::Query(e) => Some(e),
+            Error::Sink(e) => Some(&**e),
+        }
+    }
+}
+
+impl From<octocrab::Error> for Error {
+    fn from(e: octocrab::Error) -> Self {
+        Error::GitHub(e)
+    }
+}
+
+impl From<serde_urlencoded::ser::Error> for Error {
+    fn from(e: serde_urlencoded::ser::Error) -> Self {
+        Error::Query(e)
+    }
+}
+
+// With `skip`, a failed item is logged and taken as having nothing in it,
+// so that the rest of the listing is still extracted.
+pub(crate) fn skip_failed<T: Default>(result: Result<T>, skip: bool) -> Result<T> {
+    match result {
+        Err(e @ Error::Item { .. }) if skip => {
+            warn!("Skipped: {:#}", anyhow::Error::from(e));
+            Ok(T::default())
+        }
+        result => result,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn query_error() -> Error {
+        serde_urlencoded::ser::Error::Custom("unsupported value".into()).into()
+    }
+
+    #[test]
+    fn test_skip_failed_items() {
+        let failed = || {
+            Err(Error::item(
+                "files of pull request octocat/Hello-World#1".to_string(),
+                query_error(),
+            ))
+        };
+
+        assert_eq!(
+            skip_failed::<Vec<u64>>(failed(), true).unwrap(),
+            Vec::<u64>::new()
+        );
+        let e = skip_failed::<Vec<u64>>(failed(), false).unwrap_err();
+        assert_eq!(
+            format!("{:#}", anyhow::Error::from(e)),
+            "failed to fetch files of pull request octocat/Hello-World#1: \
+             failed to encode query parameters: unsupported value"
+        );
+        // Only items are skipped, not the listing they belong to
+        assert!(skip_failed::<Vec<u64>>(Err(query_error()), true).is_err());
+    }
+}
@@ -1,5 +1,8 @@
 use super::*;
+use crate::checkpoint::Cursor;
 
+use async_stream::try_stream;
+use futures::{pin_mut, Stream, TryStreamExt};
 use octocrab::models::{issues, Author};
 use url::Url;
 use serde::*;
@@ -75,26 +78,29 @@ pub struct Label {
     pub color: String,
 }
 
-#[derive(Serialize, Debug)]
-pub struct EventRec {
-    pub id: Option<i64>,
-    pub node_id: Option<String>,
-    pub url: Option<String>,
-    pub actor_id: Option<i64>,
-    pub assignee_id: Option<i64>,
-    pub assigner_id: Option<i64>,
-    pub review_requester_id: Option<i64>,
-    pub requested_reviewer_id: Option<i64>,
-    pub label: Option<String>,
-    pub milestone_title: Option<String>,
-    pub project_card_url: Option<Url>,
-    pub event: Option<String>, // Used instead of Event
-    pub commit_id: Option<String>,
-    pub commit_url: Option<Url>,
-    pub created_at: DateTime,
-    pub issue_id: i64,
-
-    pub sdc_repository: String,
+record! {
+    #[table(name = "events", watermark = created_at)]
+    #[derive(Serialize, Debug)]
+    pub struct EventRec {
+        pub id: Option<i64>,
+        pub node_id: Option<String>,
+        pub url: Option<String>,
+        pub actor_id: Option<i64>,
+        pub assignee_id: Option<i64>,
+        pub assigner_id: Option<i64>,
+        pub review_requester_id: Option<i64>,
+        pub requested_reviewer_id: Option<i64>,
+        pub label: Option<String>,
+        pub milestone_title: Option<String>,
+        pub project_card_url: Option<Url>,
+        pub event: Option<String>, // Used instead of Event
+        pub commit_id: Option<String>,
+        pub commit_url: Option<Url>,
+        pub created_at: DateTime,
+        pub issue_id: i64,
+
+        pub sdc_repository: String,
+    }
 }
 
 impl RepositryAware for EventRec {
@@ -133,6 +139,7 @@ pub struct IssueEventFetcher {
     name: String,
     since: Option<DateTime>,
     octocrab: octocrab::Octocrab,
+    cursor: Cursor,
 }
 
 impl IssueEventFetcher {
@@ -147,8 +154,14 @@ impl IssueEventFetcher {
             name,
             since,
             octocrab,
+            cursor: Cursor::default(),
         }
     }
+
+    pub fn with_cursor(mut self, cursor: Cursor) -> Self {
+        self.cursor = cursor;
+        self
+    }
 }
 
 impl UrlConstructor for IssueEventFetcher {
@@ -156,15 +169,15 @@ impl UrlConstructor for IssueEventFetcher {
         format!("{}/{}", self.owner, self.name)
     }
 
-    fn entrypoint_route(&self) -> String {
+    fn entrypoint_route(&self) -> crate::Result<String> {
         let param = Params::default();
 
-        format!(
+        Ok(format!(
             "/repos/{owner}/{repo}/issues/events?{query}",
             owner = &self.owner,
             repo = &self.name,
-            query = param.to_query(),
-        )
+            query = param.to_query()?,
+        ))
     }
 }
 
@@ -174,34 +187,26 @@ impl LoopWriter for IssueEventFetcher {
 }
 
 impl IssueEventFetcher {
-    pub async fn fetch<T: std::io::Write>(&self, mut wtr: csv::Writer<T>) -> octocrab::Result<()> {
-        let first: octocrab::Page<IssueEvent> = self
-            .octocrab
-            .get(self.entrypoint_route(), None::<&()>)
-            .await?;
-        let mut page_opt = Some(first);
-
-        while let Some(mut page) = page_opt {
-            let labels: Vec<IssueEvent> = page.take_items();
-            let mut last_update: Option<DateTime> = None;
-            for label in labels.into_iter() {
-                let mut label: EventRec = label.into();
-                label.set_repository(self.reponame());
-                wtr.serialize(&label).expect("Serialize failed");
-                last_update = label.created_at.into()
-            }
-            let next = if let Some(since) = self.since {
-                if last_update.unwrap() < since {
-                    None
-                } else {
-                    page.next.map(to_relative_uri)
+    pub fn stream(&self) -> impl Stream<Item = crate::Result<EventRec>> + '_ {
+        try_stream! {
+            let pages = self.cursor.pages::<IssueEvent>(&self.octocrab, self.entrypoint_route()?);
+            pin_mut!(pages);
+            while let Some(mut page) = pages.try_next().await? {
+                let mut last_update: Option<DateTime> = None;
+                for label in self.take_records(&mut page) {
+                    last_update = label.created_at.into();
+                    yield label;
                 }
-            } else {
-                page.next.map(to_relative_uri)
-            };
-            page_opt = self.octocrab.get_page(&next).await?;
+                if let Some(since) = self.since {
+                    if last_update.is_none_or(|last| last < since) {
+                        break;
+                    }
+                }
+            }
         }
+    }
 
-        Ok(())
+    pub async fn fetch<S: RecordSink<EventRec> + ?Sized>(&self, sink: &mut S) -> crate::Result<()> {
+        write_stream(self.stream(), sink).await
     }
 }
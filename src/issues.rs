@@ -5,39 +5,46 @@ type DateTime = chrono::DateTime<chrono::Utc>;
 
 use serde::Serialize;
 
+use async_stream::try_stream;
+use futures::{pin_mut, Stream, TryStreamExt};
+
+use crate::checkpoint::Cursor;
 use crate::*;
 
-#[derive(Serialize, Debug)]
-pub struct IssueRec {
-    pub id: i64,
-    pub node_id: String,
-    pub url: Url,
-    pub repository_url: Url,
-    pub labels_url: Url,
-    pub comments_url: Url,
-    pub events_url: Url,
-    pub html_url: Url,
-    pub number: u64,
-    pub state: IssueState,
-    pub title: String,
-    pub body: Option<String>,
-    pub body_text: Option<String>,
-    pub body_html: Option<String>,
-    pub user_id: i64,
-    pub labels: String,
-    pub assignee_id: Option<i64>,
-    pub assignees: String,
-    pub author_association: Option<AuthorAssociation>,
-    pub milestone: Option<String>,
-    pub locked: bool,
-    pub active_lock_reason: Option<String>,
-    pub comments: u32,
-    pub pull_request: Option<Url>,
-    pub closed_at: Option<DateTime>,
-    pub created_at: DateTime,
-    pub updated_at: DateTime,
-
-    pub sdc_repository: String,
+record! {
+    #[table(name = "issues", watermark = updated_at)]
+    #[derive(Serialize, Debug)]
+    pub struct IssueRec {
+        pub id: i64,
+        pub node_id: String,
+        pub url: Url,
+        pub repository_url: Url,
+        pub labels_url: Url,
+        pub comments_url: Url,
+        pub events_url: Url,
+        pub html_url: Url,
+        pub number: u64,
+        pub state: IssueState,
+        pub title: String,
+        pub body: Option<String>,
+        pub body_text: Option<String>,
+        pub body_html: Option<String>,
+        pub user_id: i64,
+        pub labels: Vec<String>,
+        pub assignee_id: Option<i64>,
+        pub assignees: String,
+        pub author_association: Option<AuthorAssociation>,
+        pub milestone: Option<String>,
+        pub locked: bool,
+        pub active_lock_reason: Option<String>,
+        pub comments: u32,
+        pub pull_request: Option<Url>,
+        pub closed_at: Option<DateTime>,
+        pub created_at: DateTime,
+        pub updated_at: DateTime,
+
+        pub sdc_repository: String,
+    }
 }
 
 impl RepositryAware for IssueRec {
@@ -49,10 +56,6 @@ impl RepositryAware for IssueRec {
 impl From<Issue> for IssueRec {
     fn from(from: Issue) -> IssueRec {
         let labels = from.labels;
-        let labels = labels
-            .iter()
-            .map(|v| v.name.clone())
-            .collect::<Vec<String>>();
         let assignees = from.assignees;
 
         IssueRec {
@@ -71,7 +74,7 @@ impl From<Issue> for IssueRec {
             body_text: from.body_text,
             body_html: from.body_html,
             user_id: from.user.id.0 as i64,
-            labels: serde_json::to_string(&labels).unwrap_or("[]".to_string()),
+            labels: labels.into_iter().map(|v| v.name).collect(),
             assignee_id: match from.assignee {
                 Some(user) => Some(user.id.0 as i64),
                 None => None,
@@ -107,6 +110,7 @@ pub struct IssueFetcher {
     name: String,
     since: Option<DateTime>,
     octocrab: octocrab::Octocrab,
+    cursor: Cursor,
 }
 
 impl IssueFetcher {
@@ -121,8 +125,14 @@ impl IssueFetcher {
             name,
             since,
             octocrab,
+            cursor: Cursor::default(),
         }
     }
+
+    pub fn with_cursor(mut self, cursor: Cursor) -> Self {
+        self.cursor = cursor;
+        self
+    }
 }
 
 impl UrlConstructor for IssueFetcher {
@@ -130,19 +140,19 @@ impl UrlConstructor for IssueFetcher {
         format!("{}/{}", self.owner, self.name)
     }
 
-    fn entrypoint_route(&self) -> String {
+    fn entrypoint_route(&self) -> crate::Result<String> {
         let param = Params {
             state: octocrab::params::State::All.into(),
             since: self.since,
             ..Default::default()
         };
 
-        format!(
+        Ok(format!(
             "/repos/{owner}/{repo}/issues?{query}",
             owner = &self.owner,
             repo = &self.name,
-            query = param.to_query(),
-        )
+            query = param.to_query()?,
+        ))
     }
 }
 
@@ -152,18 +162,20 @@ impl LoopWriter for IssueFetcher {
 }
 
 impl IssueFetcher {
-    pub async fn fetch<T: std::io::Write>(&self, mut wtr: csv::Writer<T>) -> octocrab::Result<()> {
-        let first: octocrab::Page<Issue> = self
-            .octocrab
-            .get(self.entrypoint_route(), None::<&()>)
-            .await?;
-        let mut next = self.write_and_continue(first, &mut wtr);
-
-        while let Some(page) = self.octocrab.get_page(&next).await? {
-            next = self.write_and_continue(page, &mut wtr);
+    pub fn stream(&self) -> impl Stream<Item = crate::Result<IssueRec>> + '_ {
+        try_stream! {
+            let pages = self.cursor.pages::<Issue>(&self.octocrab, self.entrypoint_route()?);
+            pin_mut!(pages);
+            while let Some(mut page) = pages.try_next().await? {
+                for record in self.take_records(&mut page) {
+                    yield record;
+                }
+            }
         }
+    }
 
-        Ok(())
+    pub async fn fetch<S: RecordSink<IssueRec> + ?Sized>(&self, sink: &mut S) -> crate::Result<()> {
+        write_stream(self.stream(), sink).await
     }
 }
 
@@ -187,7 +199,11 @@ mod tests {
 
         assert_eq!(
             record.labels,
-            "[\"bug\",\"security\",\"needs review\"]".to_string()
+            vec![
+                "bug".to_string(),
+                "security".to_string(),
+                "needs review".to_string()
+            ]
         );
         assert_eq!(record.user_id, 1);
         assert_eq!(record.assignee_id, Some(1));
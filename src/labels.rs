@@ -2,19 +2,26 @@ use octocrab::models::*;
 use url::Url;
 use serde::*;
 
+use async_stream::try_stream;
+use futures::{pin_mut, Stream, TryStreamExt};
+
+use crate::checkpoint::Cursor;
 use crate::*;
 
-#[derive(Serialize, Debug)]
-pub struct LabelRec {
-    pub id: i64,
-    pub node_id: String,
-    pub url: Url,
-    pub name: String,
-    pub description: Option<String>,
-    pub color: String,
-    pub default: bool,
-
-    pub sdc_repository: String,
+record! {
+    #[table(name = "labels")]
+    #[derive(Serialize, Debug)]
+    pub struct LabelRec {
+        pub id: i64,
+        pub node_id: String,
+        pub url: Url,
+        pub name: String,
+        pub description: Option<String>,
+        pub color: String,
+        pub default: bool,
+
+        pub sdc_repository: String,
+    }
 }
 
 impl RepositryAware for LabelRec {
@@ -43,6 +50,7 @@ pub struct LabelFetcher {
     owner: String,
     name: String,
     octocrab: octocrab::Octocrab,
+    cursor: Cursor,
 }
 
 impl LabelFetcher {
@@ -51,8 +59,14 @@ impl LabelFetcher {
             owner,
             name,
             octocrab,
+            cursor: Cursor::default(),
         }
     }
+
+    pub fn with_cursor(mut self, cursor: Cursor) -> Self {
+        self.cursor = cursor;
+        self
+    }
 }
 
 impl UrlConstructor for LabelFetcher {
@@ -60,15 +74,15 @@ impl UrlConstructor for LabelFetcher {
         format!("{}/{}", self.owner, self.name)
     }
 
-    fn entrypoint_route(&self) -> String {
+    fn entrypoint_route(&self) -> crate::Result<String> {
         let param = Params::default();
 
-        format!(
+        Ok(format!(
             "/repos/{owner}/{repo}/labels?{query}",
             owner = &self.owner,
             repo = &self.name,
-            query = param.to_query(),
-        )
+            query = param.to_query()?,
+        ))
     }
 }
 
@@ -78,17 +92,19 @@ impl LoopWriter for LabelFetcher {
 }
 
 impl LabelFetcher {
-    pub async fn fetch<T: std::io::Write>(&self, mut wtr: csv::Writer<T>) -> octocrab::Result<()> {
-        let first: octocrab::Page<Label> = self
-            .octocrab
-            .get(self.entrypoint_route(), None::<&()>)
-            .await?;
-        let mut next = self.write_and_continue(first, &mut wtr);
-
-        while let Some(page) = self.octocrab.get_page(&next).await? {
-            next = self.write_and_continue(page, &mut wtr);
+    pub fn stream(&self) -> impl Stream<Item = crate::Result<LabelRec>> + '_ {
+        try_stream! {
+            let pages = self.cursor.pages::<Label>(&self.octocrab, self.entrypoint_route()?);
+            pin_mut!(pages);
+            while let Some(mut page) = pages.try_next().await? {
+                for record in self.take_records(&mut page) {
+                    yield record;
+                }
+            }
         }
+    }
 
-        Ok(())
+    pub async fn fetch<S: RecordSink<LabelRec> + ?Sized>(&self, sink: &mut S) -> crate::Result<()> {
+        write_stream(self.stream(), sink).await
     }
 }
@@ -1,19 +1,51 @@
 extern crate serde_urlencoded;
 
+#[macro_use]
+pub mod schema;
+
 pub mod api_ext;
+pub mod checkpoint;
+pub mod checks;
+pub mod client;
 pub mod comments;
 pub mod commits;
+pub mod config;
+pub mod deployments;
+pub mod error;
 pub mod events;
 pub mod issues;
 pub mod labels;
+pub mod milestones;
+#[cfg(feature = "parquet")]
+pub mod parquet_sink;
+#[cfg(feature = "postgres")]
+pub mod postgres_sink;
 pub mod pulls;
+pub mod refs;
 pub mod releases;
+pub mod repos;
+pub mod review_comments;
 pub mod reviews;
+#[cfg(feature = "sqlite")]
+pub mod sqlite_sink;
+pub mod state;
+pub mod statuses;
+#[cfg(test)]
+mod test_server;
+pub mod timeline;
 pub mod users;
 pub mod users_detailed;
 pub mod workflows;
 
-use serde::Serialize;
+use futures::{pin_mut, Stream, TryStreamExt};
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use std::io::{self, Write};
+use std::str::FromStr;
+
+pub use error::{Error, Result};
+
+// Pull requests whose files, commits or reviews are fetched at a time
+pub const PULL_CONCURRENCY: usize = 8;
 
 #[derive(Serialize, Debug)]
 pub struct Params {
@@ -28,8 +60,8 @@ pub struct Params {
 }
 
 impl Params {
-    pub fn to_query(&self) -> String {
-        serde_urlencoded::to_string(self).unwrap()
+    pub fn to_query(&self) -> crate::Result<String> {
+        Ok(serde_urlencoded::to_string(self)?)
     }
 }
 
@@ -72,24 +104,302 @@ pub fn to_relative_uri(uri: http::Uri) -> http::Uri {
 pub trait UrlConstructor {
     fn reponame(&self) -> String;
 
-    fn entrypoint_route(&self) -> String;
+    fn entrypoint_route(&self) -> crate::Result<String>;
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Default, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum OutputFormat {
+    #[default]
+    Csv,
+    #[serde(alias = "ndjson")]
+    Jsonl,
+    #[cfg(feature = "parquet")]
+    Parquet,
+}
+
+impl FromStr for OutputFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "csv" => Ok(OutputFormat::Csv),
+            "jsonl" | "ndjson" => Ok(OutputFormat::Jsonl),
+            #[cfg(feature = "parquet")]
+            "parquet" => Ok(OutputFormat::Parquet),
+            _ => Err(format!("unknown output format: {}", s)),
+        }
+    }
+}
+
+impl OutputFormat {
+    pub fn extension(&self) -> &'static str {
+        match self {
+            OutputFormat::Csv => "csv",
+            OutputFormat::Jsonl => "jsonl",
+            #[cfg(feature = "parquet")]
+            OutputFormat::Parquet => "parquet",
+        }
+    }
+}
+
+// Receives records produced by fetchers.
+// Implement this to consume records in-process (push into a Vec, send them
+// to a channel or a database) instead of writing them out as text.
+pub trait RecordSink<R> {
+    fn write(&mut self, record: R) -> anyhow::Result<()>;
+
+    fn flush(&mut self) -> anyhow::Result<()> {
+        Ok(())
+    }
+
+    // Called once no more records will be written, e.g. to write a file footer.
+    fn close(&mut self) -> anyhow::Result<()> {
+        self.flush()
+    }
+}
+
+impl<R> RecordSink<R> for Vec<R> {
+    fn write(&mut self, record: R) -> anyhow::Result<()> {
+        self.push(record);
+        Ok(())
+    }
+}
+
+impl<R> RecordSink<R> for std::sync::mpsc::Sender<R> {
+    fn write(&mut self, record: R) -> anyhow::Result<()> {
+        self.send(record)
+            .map_err(|_| anyhow::anyhow!("record receiver has been dropped"))
+    }
+}
+
+impl<R, S: RecordSink<R> + ?Sized> RecordSink<R> for &mut S {
+    fn write(&mut self, record: R) -> anyhow::Result<()> {
+        (**self).write(record)
+    }
+
+    fn flush(&mut self) -> anyhow::Result<()> {
+        (**self).flush()
+    }
+
+    fn close(&mut self) -> anyhow::Result<()> {
+        (**self).close()
+    }
+}
+
+impl<R, S: RecordSink<R> + ?Sized> RecordSink<R> for Box<S> {
+    fn write(&mut self, record: R) -> anyhow::Result<()> {
+        (**self).write(record)
+    }
+
+    fn flush(&mut self) -> anyhow::Result<()> {
+        (**self).flush()
+    }
+
+    fn close(&mut self) -> anyhow::Result<()> {
+        (**self).close()
+    }
+}
+
+// CSV cells cannot hold nested values, so arrays and objects in a record
+// (e.g. `IssueRec.labels`, `JobRec.steps`) are encoded as a JSON string.
+pub struct CsvSink<W: io::Write> {
+    wtr: csv::Writer<W>,
+    write_headers: bool,
+}
+
+impl<W: io::Write> CsvSink<W> {
+    pub fn new(inner: W) -> Self {
+        Self {
+            wtr: csv::WriterBuilder::new()
+                .has_headers(false)
+                .from_writer(inner),
+            write_headers: true,
+        }
+    }
+
+    // Leave out the header row, e.g. when appending to an existing file
+    pub fn has_headers(mut self, yes: bool) -> Self {
+        self.write_headers = yes;
+        self
+    }
+}
+
+impl<R: Serialize, W: io::Write> RecordSink<R> for CsvSink<W> {
+    fn write(&mut self, record: R) -> anyhow::Result<()> {
+        let (headers, row) = to_csv_row(&record)?;
+        if self.write_headers {
+            self.wtr.write_record(&headers)?;
+            self.write_headers = false;
+        }
+        self.wtr.write_record(&row)?;
+        Ok(())
+    }
+
+    fn flush(&mut self) -> anyhow::Result<()> {
+        self.wtr.flush()?;
+        Ok(())
+    }
+}
+
+// Writes one JSON document per line, keeping nested values as they are.
+pub struct JsonSink<W: io::Write> {
+    wtr: io::BufWriter<W>,
+}
+
+impl<W: io::Write> JsonSink<W> {
+    pub fn new(inner: W) -> Self {
+        Self {
+            wtr: io::BufWriter::new(inner),
+        }
+    }
+}
+
+impl<R: Serialize, W: io::Write> RecordSink<R> for JsonSink<W> {
+    fn write(&mut self, record: R) -> anyhow::Result<()> {
+        serde_json::to_writer(&mut self.wtr, &record)?;
+        self.wtr.write_all(b"\n")?;
+        Ok(())
+    }
+
+    fn flush(&mut self) -> anyhow::Result<()> {
+        self.wtr.flush()?;
+        Ok(())
+    }
+}
+
+pub fn output_sink<R, W>(format: OutputFormat, inner: W) -> anyhow::Result<Box<dyn RecordSink<R>>>
+where
+    R: Serialize + schema::Table + 'static,
+    W: io::Write + Send + 'static,
+{
+    Ok(match format {
+        OutputFormat::Csv => Box::new(CsvSink::new(inner)),
+        OutputFormat::Jsonl => Box::new(JsonSink::new(inner)),
+        #[cfg(feature = "parquet")]
+        OutputFormat::Parquet => Box::new(parquet_sink::ParquetSink::new::<R>(inner)?),
+    })
+}
+
+fn to_csv_row<R: Serialize>(record: &R) -> serde_json::Result<(Vec<String>, Vec<String>)> {
+    use serde::ser::Error;
+    use serde_json::Value;
+
+    let map = match serde_json::to_value(record)? {
+        Value::Object(map) => map,
+        _ => return Err(serde_json::Error::custom("record must be a struct")),
+    };
+    let mut headers = Vec::with_capacity(map.len());
+    let mut row = Vec::with_capacity(map.len());
+    for (key, value) in map.into_iter() {
+        headers.push(key);
+        row.push(match value {
+            Value::Null => String::new(),
+            Value::String(s) => s,
+            other => other.to_string(),
+        });
+    }
+    Ok((headers, row))
+}
+
+// Walk a paginated listing from `route`, following `page.next` until it runs out.
+// Nothing is requested until the stream is polled, so a consumer can stop
+// early (e.g. on reaching the `since` cutoff) just by dropping it.
+pub fn pages<'a, M: DeserializeOwned + 'a>(
+    octocrab: &'a octocrab::Octocrab,
+    route: String,
+) -> impl Stream<Item = crate::Result<octocrab::Page<M>>> + 'a {
+    async_stream::try_stream! {
+        let mut page_opt: Option<octocrab::Page<M>> =
+            Some(octocrab.get(route, None::<&()>).await?);
+        while let Some(mut page) = page_opt {
+            let next = page.next.take().map(to_relative_uri);
+            yield page;
+            page_opt = octocrab.get_page(&next).await?;
+        }
+    }
+}
+
+// Drive a record stream to completion, writing every record into the sink.
+pub async fn write_stream<R, S, T>(stream: T, sink: &mut S) -> crate::Result<()>
+where
+    S: RecordSink<R> + ?Sized,
+    T: Stream<Item = crate::Result<R>>,
+{
+    pin_mut!(stream);
+    while let Some(record) = stream.try_next().await? {
+        sink.write(record).map_err(Error::Sink)?;
+    }
+    Ok(())
 }
 
 pub trait LoopWriter: UrlConstructor {
     type Model;
     type Record: serde::Serialize + RepositryAware + From<Self::Model>;
 
-    fn write_and_continue<T: std::io::Write>(
-        &self,
-        mut page: octocrab::Page<Self::Model>,
-        wtr: &mut csv::Writer<T>,
-    ) -> Option<http::Uri> {
+    fn take_records(&self, page: &mut octocrab::Page<Self::Model>) -> Vec<Self::Record> {
         let labels: Vec<Self::Model> = page.take_items();
-        for label in labels.into_iter() {
-            let mut label: Self::Record = label.into();
-            label.set_repository(self.reponame());
-            wtr.serialize(&label).expect("Serialize failed");
+        labels
+            .into_iter()
+            .map(|label| {
+                let mut label: Self::Record = label.into();
+                label.set_repository(self.reponame());
+                label
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[derive(Serialize)]
+    struct NestedRec {
+        id: i64,
+        labels: Vec<String>,
+        body: Option<String>,
+    }
+
+    fn write_all<S: RecordSink<NestedRec>>(mut sink: S) {
+        for id in 1..=2 {
+            let rec = NestedRec {
+                id,
+                labels: vec!["bug".to_string(), "good first issue".to_string()],
+                body: None,
+            };
+            sink.write(rec).unwrap();
         }
-        page.next.map(to_relative_uri)
+        sink.flush().unwrap();
+    }
+
+    #[test]
+    fn test_csv_encodes_nested_values_as_json() {
+        let mut out = vec![];
+        write_all(CsvSink::new(&mut out));
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            "id,labels,body\n\
+             1,\"[\"\"bug\"\",\"\"good first issue\"\"]\",\n\
+             2,\"[\"\"bug\"\",\"\"good first issue\"\"]\",\n"
+        );
+    }
+
+    #[test]
+    fn test_jsonl_keeps_nested_values() {
+        let mut out = vec![];
+        write_all(JsonSink::new(&mut out));
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            "{\"id\":1,\"labels\":[\"bug\",\"good first issue\"],\"body\":null}\n\
+             {\"id\":2,\"labels\":[\"bug\",\"good first issue\"],\"body\":null}\n"
+        );
+    }
+
+    #[test]
+    fn test_vec_collects_records() {
+        let mut records: Vec<NestedRec> = vec![];
+        write_all(&mut records);
+        assert_eq!(records.iter().map(|r| r.id).collect::<Vec<_>>(), vec![1, 2]);
     }
 }
@@ -1,18 +1,32 @@
 use anyhow::*;
+use async_stream::try_stream;
 use chrono::{DateTime, Duration, Utc};
-use csv::WriterBuilder;
+use futures::{pin_mut, stream, Stream, StreamExt, TryStreamExt};
 use log::*;
 use serde::*;
 use structopt::StructOpt;
 
+use std::fs;
 use std::io;
+use std::path::PathBuf;
+use std::str::FromStr;
 
 extern crate octx;
+use octx::checkpoint::{write_resumable, Checkpoint, Cursor};
+use octx::client::{Credentials, RateLimiter, RetryPolicy};
+use octx::config::Config;
+use octx::repos::{OrgRepoFetcher, RepoFilter, VISIBILITIES};
+use octx::state::{StateDir, WatermarkSink};
 use octx::{
-    comments::CommentFetcher, commits::CommitFetcher, events::IssueEventFetcher,
-    issues::IssueFetcher, labels::LabelFetcher, pulls::PullFileFetcher, releases::ReleaseFetcher,
-    reviews::ReviewFetcher, users::UserFetcher, users_detailed::UserDetailedFetcher,
-    workflows::JobFetcher, workflows::RunFetcher, workflows::WorkFlowFetcher,
+    checks::CheckRunFetcher, checks::CheckSuiteFetcher, comments::CommentFetcher,
+    commits::CommitFetcher, deployments::DeploymentFetcher, deployments::DeploymentStatusFetcher,
+    events::IssueEventFetcher, issues::IssueFetcher, labels::LabelFetcher,
+    milestones::MilestoneFetcher, output_sink, pulls::PullFetcher, pulls::PullFileFetcher,
+    refs::BranchFetcher, refs::BranchProtectionFetcher, refs::TagFetcher, releases::ReleaseFetcher,
+    review_comments::ReviewCommentFetcher, reviews::ReviewFetcher, schema::SchemaFormat,
+    schema::Table, statuses::StatusFetcher, timeline::TimelineFetcher, users::UserFetcher,
+    users_detailed::UserDetailedFetcher, workflows::JobFetcher, workflows::RunFetcher,
+    workflows::WorkFlowFetcher, write_stream, CsvSink, OutputFormat, RecordSink,
 };
 
 #[derive(StructOpt)]
@@ -32,14 +46,25 @@ use octx::{
 
 EXAMPLE:
     octx --issues rust-lang rust --days-ago 30
+    octx --all --out-dir ./dump rust-lang rust
+    octx schema --format postgres issues comments
+    octx run --config octx.toml
 ")]
+// Otherwise a repository named like a subcommand, e.g. `octx --issues acme run`,
+// would be taken for it
+#[structopt(setting = structopt::clap::AppSettings::ArgsNegateSubcommands)]
 struct Command {
+    #[structopt(subcommand)]
+    subcommand: Option<Subcommand>,
     /// Extract issues - including pull requests
     #[structopt(long = "issues")]
     target_issues: bool,
     /// Extract issue events
     #[structopt(long = "events")]
     target_events: bool,
+    /// Extract the timeline of issues and pull requests, such as cross-references
+    #[structopt(long = "timeline")]
+    target_timeline: bool,
     /// Extract issue comments
     #[structopt(long = "comments")]
     target_comments: bool,
@@ -49,6 +74,18 @@ struct Command {
     /// Extract issue labels
     #[structopt(long = "labels")]
     target_labels: bool,
+    /// Extract milestones
+    #[structopt(long = "milestones")]
+    target_milestones: bool,
+    /// Extract branches
+    #[structopt(long = "branches")]
+    target_branches: bool,
+    /// Extract tags
+    #[structopt(long = "tags")]
+    target_tags: bool,
+    /// Extract protection rules of protected branches - requires admin access to the repository
+    #[structopt(long = "branch-protections")]
+    target_branch_protections: bool,
     /// Extract releases
     #[structopt(long = "releases")]
     target_releases: bool,
@@ -58,6 +95,9 @@ struct Command {
     /// Extract users - owner/name is not required for this option
     #[structopt(long = "users")]
     target_users: bool,
+    /// Extract pull requests, with their head and base, merge status and size
+    #[structopt(long = "pulls")]
+    target_pulls: bool,
     /// Extract Files included in pull requests
     #[structopt(long = "pull-request-files")]
     target_pull_files: bool,
@@ -76,8 +116,36 @@ struct Command {
     /// Extract pull request reviews
     #[structopt(long = "reviews")]
     target_reviews: bool,
+    /// Extract deployments
+    #[structopt(long = "deployments")]
+    target_deployments: bool,
+    /// Extract statuses of deployments
+    #[structopt(long = "deployment-statuses")]
+    target_deployment_statuses: bool,
+    /// Extract check suites of commits, including those of apps other than Actions
+    #[structopt(long = "check-suites")]
+    target_check_suites: bool,
+    /// Extract check runs of commits, including those of apps other than Actions
+    #[structopt(long = "check-runs")]
+    target_check_runs: bool,
+    /// Extract commit statuses set through the status API
+    #[structopt(long = "statuses")]
+    target_statuses: bool,
+    /// Extract pull request review comments, i.e. comments on lines of the diff
+    #[structopt(long = "review-comments")]
+    target_review_comments: bool,
+    /// Extract a target, named as its option without dashes, e.g. issues or pull-request-files.
+    /// Can be given more than once
+    #[structopt(long = "target", number_of_values = 1)]
+    targets: Vec<Target>,
+    /// Extract every target of the repository, i.e. all but --users, --users-detailed and
+    /// --branch-protections, which needs admin access
+    #[structopt(long = "all")]
+    target_all: bool,
     /// Extract models created after N days ago.
-    /// Only valid for --issues, --comments, --events --commits, --pull-request-files
+    /// Only valid for --issues, --events, --timeline, --comments, --commits, --pulls,
+    /// --pull-request-files, --pull-request-commits, --runs, --jobs, --reviews, --deployments,
+    /// --deployment-statuses, --check-suites, --check-runs, --statuses and --review-comments
     #[structopt(long = "days-ago")]
     days_ago: Option<i64>,
     /// Extract models created after specified date.
@@ -85,6 +153,63 @@ struct Command {
     /// To see example, use e.g. `date --iso-8601=seconds`
     #[structopt(long = "since-date")]
     since_date: Option<String>,
+    /// Keep the latest updated_at/created_at extracted per repository and target in this directory,
+    /// and extract from there on the next run unless --days-ago or --since-date is given
+    #[structopt(long = "state-dir", parse(from_os_str))]
+    state_dir: Option<PathBuf>,
+    /// Output format: csv, jsonl or parquet (only when built with the `parquet` feature).
+    /// jsonl keeps nested values such as labels or job steps as JSON arrays/objects
+    #[structopt(long = "format", default_value = "csv")]
+    format: OutputFormat,
+    /// Write records to this file instead of stdout
+    #[structopt(short = "o", long = "output", parse(from_os_str))]
+    output: Option<PathBuf>,
+    /// Write each target to its own file in this directory, e.g. issues.csv
+    #[structopt(long = "out-dir", parse(from_os_str), conflicts_with = "output")]
+    out_dir: Option<PathBuf>,
+    /// Save the progress of the extraction to this file while running.
+    /// It is removed when the extraction completes
+    #[structopt(long = "checkpoint", parse(from_os_str))]
+    checkpoint: Option<PathBuf>,
+    /// Continue an interrupted extraction from --checkpoint, appending to --output
+    #[structopt(long = "resume", requires = "checkpoint")]
+    resume: bool,
+    /// Upsert records into a SQLite database instead of writing them to stdout.
+    /// Each target gets its own table, keyed by id (or sha for commits)
+    #[cfg(feature = "sqlite")]
+    #[structopt(long = "sqlite", parse(from_os_str))]
+    sqlite: Option<PathBuf>,
+    /// Load records into PostgreSQL instead of writing them to stdout.
+    /// Takes connection parameters, e.g. "host=localhost user=octx dbname=github"
+    #[cfg(feature = "postgres")]
+    #[structopt(long = "postgres")]
+    postgres: Option<String>,
+    /// Extract every repository of the organization instead of owner/name
+    #[structopt(long = "org", conflicts_with_all = &["owner", "name"])]
+    org: Option<String>,
+    /// Leave out archived repositories with --org
+    #[structopt(long = "skip-archived")]
+    skip_archived: bool,
+    /// Leave out forked repositories with --org
+    #[structopt(long = "skip-forks")]
+    skip_forks: bool,
+    /// Only extract repositories of this visibility with --org
+    #[structopt(long = "visibility", possible_values = VISIBILITIES)]
+    visibility: Option<String>,
+    /// Extract this many repositories at a time with --org.
+    /// Cannot be combined with --checkpoint
+    #[structopt(long = "concurrency", default_value = "1")]
+    concurrency: usize,
+    /// Send a request up to this many times on 5xx responses, connection errors and timeouts
+    #[structopt(long = "max-attempts", default_value = "5")]
+    max_attempts: usize,
+    /// Give up retrying a request this many seconds after its first attempt
+    #[structopt(long = "max-retry-secs", default_value = "300")]
+    max_retry_secs: u64,
+    /// Log and leave out a pull request or workflow run whose files, commits, reviews or jobs
+    /// cannot be fetched, instead of stopping the extraction
+    #[structopt(long = "skip-errors")]
+    skip_errors: bool,
     /// Extract Workflow runs for specified workflow file.
     #[structopt(long = "workflow-file")]
     workflow_file: Option<String>,
@@ -97,124 +222,1315 @@ struct Command {
     name: Option<String>,
 }
 
+impl Command {
+    // Targets given, in the order of the options followed by --target
+    fn targets(&self) -> Vec<Target> {
+        if self.target_all {
+            return Target::ALL
+                .iter()
+                .filter(|target| !target.global() && !target.admin())
+                .copied()
+                .collect();
+        }
+        let mut targets: Vec<Target> = [
+            (self.target_users_detailed, Target::UsersDetailed),
+            (self.target_users, Target::Users),
+            (self.target_issues, Target::Issues),
+            (self.target_events, Target::Events),
+            (self.target_timeline, Target::Timeline),
+            (self.target_comments, Target::Comments),
+            (self.target_commits, Target::Commits),
+            (self.target_pulls, Target::Pulls),
+            (self.target_pull_files, Target::PullRequestFiles),
+            (self.target_pull_commits, Target::PullRequestCommits),
+            (self.target_labels, Target::Labels),
+            (self.target_milestones, Target::Milestones),
+            (self.target_branches, Target::Branches),
+            (self.target_tags, Target::Tags),
+            (self.target_branch_protections, Target::BranchProtections),
+            (self.target_releases, Target::Releases),
+            (self.target_workflows, Target::Workflows),
+            (self.target_runs, Target::Runs),
+            (self.target_jobs, Target::Jobs),
+            (self.target_deployments, Target::Deployments),
+            (self.target_deployment_statuses, Target::DeploymentStatuses),
+            (self.target_check_suites, Target::CheckSuites),
+            (self.target_check_runs, Target::CheckRuns),
+            (self.target_statuses, Target::Statuses),
+            (self.target_reviews, Target::Reviews),
+            (self.target_review_comments, Target::ReviewComments),
+        ]
+        .iter()
+        .filter(|(given, _)| *given)
+        .map(|(_, target)| *target)
+        .collect();
+        for target in self.targets.iter() {
+            if !targets.contains(target) {
+                targets.push(*target);
+            }
+        }
+        targets
+    }
+}
+
+#[derive(StructOpt)]
+enum Subcommand {
+    /// Run the extractions described in a TOML file
+    Run {
+        #[structopt(long = "config", parse(from_os_str))]
+        config: PathBuf,
+    },
+    /// Print the columns of targets without accessing GitHub
+    Schema {
+        /// Schema format: postgres, sqlite, bigquery (JSON schema) or markdown
+        #[structopt(long = "format", default_value = "markdown")]
+        format: SchemaFormat,
+        /// Targets, named as their options without dashes, e.g. issues or pull-request-files
+        #[structopt(name = "target", required = true)]
+        targets: Vec<Target>,
+    },
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Target {
+    Issues,
+    Events,
+    Timeline,
+    Comments,
+    Commits,
+    Labels,
+    Milestones,
+    Branches,
+    Tags,
+    BranchProtections,
+    Releases,
+    UsersDetailed,
+    Users,
+    Pulls,
+    PullRequestFiles,
+    PullRequestCommits,
+    Workflows,
+    Runs,
+    Jobs,
+    Deployments,
+    DeploymentStatuses,
+    CheckSuites,
+    CheckRuns,
+    Statuses,
+    Reviews,
+    ReviewComments,
+}
+
+impl FromStr for Target {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Target::ALL
+            .iter()
+            .find(|target| target.name() == s)
+            .copied()
+            .ok_or_else(|| format!("unknown target: {}", s))
+    }
+}
+
+impl Target {
+    const ALL: [Target; 26] = [
+        Target::Issues,
+        Target::Events,
+        Target::Timeline,
+        Target::Comments,
+        Target::Commits,
+        Target::Labels,
+        Target::Milestones,
+        Target::Branches,
+        Target::Tags,
+        Target::BranchProtections,
+        Target::Releases,
+        Target::UsersDetailed,
+        Target::Users,
+        Target::Pulls,
+        Target::PullRequestFiles,
+        Target::PullRequestCommits,
+        Target::Workflows,
+        Target::Runs,
+        Target::Jobs,
+        Target::Deployments,
+        Target::DeploymentStatuses,
+        Target::CheckSuites,
+        Target::CheckRuns,
+        Target::Statuses,
+        Target::Reviews,
+        Target::ReviewComments,
+    ];
+
+    fn name(&self) -> &'static str {
+        match self {
+            Target::Issues => "issues",
+            Target::Events => "events",
+            Target::Timeline => "timeline",
+            Target::Comments => "comments",
+            Target::Commits => "commits",
+            Target::Labels => "labels",
+            Target::Milestones => "milestones",
+            Target::Branches => "branches",
+            Target::Tags => "tags",
+            Target::BranchProtections => "branch-protections",
+            Target::Releases => "releases",
+            Target::UsersDetailed => "users-detailed",
+            Target::Users => "users",
+            Target::Pulls => "pulls",
+            Target::PullRequestFiles => "pull-request-files",
+            Target::PullRequestCommits => "pull-request-commits",
+            Target::Workflows => "workflows",
+            Target::Runs => "runs",
+            Target::Jobs => "jobs",
+            Target::Deployments => "deployments",
+            Target::DeploymentStatuses => "deployment-statuses",
+            Target::CheckSuites => "check-suites",
+            Target::CheckRuns => "check-runs",
+            Target::Statuses => "statuses",
+            Target::Reviews => "reviews",
+            Target::ReviewComments => "review-comments",
+        }
+    }
+
+    // Whether the target is extracted once rather than per repository
+    fn global(&self) -> bool {
+        matches!(self, Target::UsersDetailed | Target::Users)
+    }
+
+    // Whether the target needs admin access, which `--all` cannot assume
+    fn admin(&self) -> bool {
+        matches!(self, Target::BranchProtections)
+    }
+
+    // Whether the target's fetcher takes `since`
+    fn incremental(&self) -> bool {
+        !matches!(
+            self,
+            Target::Labels
+                | Target::Milestones
+                | Target::Branches
+                | Target::Tags
+                | Target::BranchProtections
+                | Target::Releases
+                | Target::UsersDetailed
+                | Target::Users
+                | Target::Workflows
+        )
+    }
+
+    fn schema(&self, format: SchemaFormat) -> String {
+        self.with_table(Render(format))
+    }
+
+    // Hands the record type of the target to `f`
+    fn with_table<F: WithTable>(&self, f: F) -> F::Output {
+        use octx::{
+            checks::CheckRunRec, checks::CheckSuiteRec, comments::CommentRec, commits::CommitRec,
+            deployments::DeploymentRec, deployments::DeploymentStatusRec, events::EventRec,
+            issues::IssueRec, labels::LabelRec, milestones::MilestoneRec, pulls::PrCommitRec,
+            pulls::PullRequestFile, pulls::PullRequestRec, refs::BranchProtectionRec,
+            refs::BranchRec, refs::TagRec, releases::ReleaseRec, review_comments::ReviewCommentRec,
+            reviews::ReviewRec, statuses::StatusRec, timeline::TimelineRec, users::UserRec,
+            users_detailed::UserDeailed, workflows::JobRec, workflows::RunRec,
+            workflows::WorkFlowRec,
+        };
+
+        match self {
+            Target::Issues => f.call::<IssueRec>(),
+            Target::Events => f.call::<EventRec>(),
+            Target::Timeline => f.call::<TimelineRec>(),
+            Target::Comments => f.call::<CommentRec>(),
+            Target::Commits => f.call::<CommitRec>(),
+            Target::Labels => f.call::<LabelRec>(),
+            Target::Milestones => f.call::<MilestoneRec>(),
+            Target::Branches => f.call::<BranchRec>(),
+            Target::Tags => f.call::<TagRec>(),
+            Target::BranchProtections => f.call::<BranchProtectionRec>(),
+            Target::Releases => f.call::<ReleaseRec>(),
+            Target::UsersDetailed => f.call::<UserDeailed>(),
+            Target::Users => f.call::<UserRec>(),
+            Target::Pulls => f.call::<PullRequestRec>(),
+            Target::PullRequestFiles => f.call::<PullRequestFile>(),
+            Target::PullRequestCommits => f.call::<PrCommitRec>(),
+            Target::Workflows => f.call::<WorkFlowRec>(),
+            Target::Runs => f.call::<RunRec>(),
+            Target::Jobs => f.call::<JobRec>(),
+            Target::Deployments => f.call::<DeploymentRec>(),
+            Target::DeploymentStatuses => f.call::<DeploymentStatusRec>(),
+            Target::CheckSuites => f.call::<CheckSuiteRec>(),
+            Target::CheckRuns => f.call::<CheckRunRec>(),
+            Target::Statuses => f.call::<StatusRec>(),
+            Target::Reviews => f.call::<ReviewRec>(),
+            Target::ReviewComments => f.call::<ReviewCommentRec>(),
+        }
+    }
+}
+
+// Something done with the record type of a target, see `Target::with_table`
+trait WithTable {
+    type Output;
+
+    fn call<R: Table>(self) -> Self::Output;
+}
+
+struct Render(SchemaFormat);
+
+impl WithTable for Render {
+    type Output = String;
+
+    fn call<R: Table>(self) -> String {
+        self.0.render::<R>()
+    }
+}
+
 #[derive(Deserialize, Debug)]
 struct Env {
     github_api_token: Option<String>,
-    github_api_url: String,
+    github_api_url: Option<String>,
     github_app_id: Option<u64>,
     github_app_private_key_path: Option<String>,
     github_app_installation_id: Option<u64>,
 }
 
-#[tokio::main]
-async fn main() -> octocrab::Result<()> {
-    env_logger::init();
-    let config: Env = envy::from_env()
-        .context("while reading from environment")
-        .unwrap();
-    let args: Command = Command::from_args();
-    let builder = octocrab::Octocrab::builder().base_uri(config.github_api_url)?;
-    let octocrab = match (
-        config.github_api_token,
-        config.github_app_id,
-        config.github_app_private_key_path,
-        config.github_app_installation_id,
+#[derive(Clone)]
+struct Output {
+    format: OutputFormat,
+    path: Option<PathBuf>,
+    out_dir: Option<PathBuf>,
+    checkpoint: Option<PathBuf>,
+    // Progress of the interrupted extraction being resumed
+    resume: Option<Checkpoint>,
+    #[cfg(feature = "sqlite")]
+    sqlite: Option<PathBuf>,
+    #[cfg(feature = "postgres")]
+    postgres: Option<String>,
+}
+
+impl Output {
+    // The output of one target, in its own file with --out-dir
+    fn of(&self, target: Target) -> Output {
+        let mut output = self.clone();
+        if let Some(dir) = &self.out_dir {
+            let file = format!("{}.{}", target.name(), self.format.extension());
+            output.path = Some(dir.join(file));
+        }
+        output
+    }
+
+    fn sink<R: Serialize + Table + 'static>(&self) -> Result<Box<dyn RecordSink<R>>> {
+        #[cfg(feature = "sqlite")]
+        if let Some(path) = &self.sqlite {
+            return Ok(Box::new(octx::sqlite_sink::SqliteSink::open::<R>(path)?));
+        }
+        #[cfg(feature = "postgres")]
+        if let Some(params) = &self.postgres {
+            return Ok(Box::new(octx::postgres_sink::PostgresSink::connect::<R>(
+                params,
+            )?));
+        }
+        let path = match (&self.path, &self.resume) {
+            (Some(path), _) => path,
+            (None, Some(_)) => bail!("--resume needs --output to append to"),
+            (None, None) => return output_sink(self.format, io::stdout()),
+        };
+        let checkpoint = match &self.resume {
+            Some(checkpoint) => checkpoint,
+            None => return output_sink(self.format, fs::File::create(path)?),
+        };
+
+        // Cut off whatever was written after the checkpoint was saved
+        let len = checkpoint
+            .output_len
+            .context("the checkpoint was not saved for a file output")?;
+        let file = fs::OpenOptions::new().write(true).open(path)?;
+        file.set_len(len)?;
+        let file = fs::OpenOptions::new().append(true).open(path)?;
+        match self.format {
+            OutputFormat::Csv => Ok(Box::new(CsvSink::new(file).has_headers(len == 0))),
+            OutputFormat::Jsonl => output_sink(self.format, file),
+            #[allow(unreachable_patterns)]
+            _ => bail!("--resume cannot append to a {:?} file", self.format),
+        }
+    }
+
+    fn check_targets(&self, targets: &[Target]) -> Result<()> {
+        if targets.len() > 1 {
+            if self.path.is_some() || self.checkpoint.is_some() {
+                bail!("--output and --checkpoint take a single target; use --out-dir");
+            }
+            if self.out_dir.is_none() && !self.to_database() {
+                bail!("more than one target needs --out-dir");
+            }
+        }
+        if let Some(dir) = &self.out_dir {
+            fs::create_dir_all(dir)?;
+        }
+        Ok(())
+    }
+
+    // Each target has its own table in a database
+    fn to_database(&self) -> bool {
+        #[cfg(feature = "sqlite")]
+        if self.sqlite.is_some() {
+            return true;
+        }
+        #[cfg(feature = "postgres")]
+        if self.postgres.is_some() {
+            return true;
+        }
+        false
+    }
+
+    fn save_checkpoint(&self, path: &PathBuf, checkpoint: &Checkpoint) -> Result<()> {
+        let mut checkpoint = checkpoint.clone();
+        if let Some(out) = &self.path {
+            checkpoint.output_len = Some(fs::metadata(out)?.len());
+        }
+        checkpoint.save(path)
+    }
+}
+
+// A repository to extract; users targets run once with an empty one
+#[derive(Debug, Clone, Default)]
+struct Repo {
+    owner: String,
+    name: String,
+}
+
+impl Repo {
+    fn full_name(&self) -> String {
+        format!("{}/{}", self.owner, self.name)
+    }
+}
+
+impl FromStr for Repo {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.split_once('/') {
+            Some((owner, name)) if !owner.is_empty() && !name.is_empty() => Ok(Repo {
+                owner: owner.to_string(),
+                name: name.to_string(),
+            }),
+            _ => bail!("repository must be owner/name: {}", s),
+        }
+    }
+}
+
+// What a fetcher is given to extract one repository
+struct Scope {
+    owner: String,
+    name: String,
+    since: Option<DateTime<Utc>>,
+    cursor: Cursor,
+}
+
+// Turns a fetcher into a stream of its records that owns the fetcher, so the
+// stream can outlive the function building it.
+macro_rules! records {
+    ($fetcher:expr, |$f:ident| $stream:expr) => {{
+        let $f = $fetcher;
+        try_stream! {
+            let records = $stream;
+            pin_mut!(records);
+            while let Some(record) = records.try_next().await? {
+                yield record;
+            }
+        }
+    }};
+}
+
+struct Extraction {
+    octocrab: octocrab::Octocrab,
+    output: Output,
+    state: Option<StateDir>,
+    // --days-ago or --since-date, which take precedence over the state
+    since: Option<DateTime<Utc>>,
+    workflow_file: Option<String>,
+    run_id: Option<String>,
+    // Repositories extracted at a time
+    concurrency: usize,
+    skip_errors: bool,
+}
+
+impl Extraction {
+    async fn extract_targets(&self, targets: &[Target], repos: &[Repo]) -> Result<()> {
+        for target in targets.iter().copied() {
+            if target.global() {
+                self.extract(target, &[Repo::default()]).await?;
+            } else {
+                self.extract(target, repos).await?;
+            }
+        }
+        Ok(())
+    }
+
+    async fn extract(&self, target: Target, repos: &[Repo]) -> Result<()> {
+        let octocrab = &self.octocrab;
+        let output = &self.output.of(target);
+        match target {
+            Target::Issues => {
+                self.run(target, output, repos, |s| {
+                    let fetcher = IssueFetcher::new(s.owner, s.name, s.since, octocrab.clone());
+                    records!(fetcher.with_cursor(s.cursor), |f| f.stream())
+                })
+                .await
+            }
+            Target::Events => {
+                self.run(target, output, repos, |s| {
+                    let fetcher =
+                        IssueEventFetcher::new(s.owner, s.name, s.since, octocrab.clone());
+                    records!(fetcher.with_cursor(s.cursor), |f| f.stream())
+                })
+                .await
+            }
+            Target::Timeline => {
+                self.run(target, output, repos, |s| {
+                    let fetcher = TimelineFetcher::new(s.owner, s.name, s.since, octocrab.clone())
+                        .with_skip_errors(self.skip_errors);
+                    records!(fetcher.with_cursor(s.cursor), |f| f.stream())
+                })
+                .await
+            }
+            Target::Comments => {
+                self.run(target, output, repos, |s| {
+                    let fetcher = CommentFetcher::new(s.owner, s.name, s.since, octocrab.clone());
+                    records!(fetcher.with_cursor(s.cursor), |f| f.stream())
+                })
+                .await
+            }
+            Target::Commits => {
+                self.run(target, output, repos, |s| {
+                    let fetcher = CommitFetcher::new(s.owner, s.name, s.since, octocrab.clone());
+                    records!(fetcher.with_cursor(s.cursor), |f| f.stream())
+                })
+                .await
+            }
+            Target::Pulls => {
+                self.run(target, output, repos, |s| {
+                    let fetcher = PullFetcher::new(s.owner, s.name, s.since, octocrab.clone())
+                        .with_skip_errors(self.skip_errors);
+                    records!(fetcher.with_cursor(s.cursor), |f| f.stream())
+                })
+                .await
+            }
+            Target::PullRequestFiles => {
+                self.run(target, output, repos, |s| {
+                    let fetcher = PullFileFetcher::new(s.owner, s.name, s.since, octocrab.clone())
+                        .with_skip_errors(self.skip_errors);
+                    records!(fetcher.with_cursor(s.cursor), |f| f.stream())
+                })
+                .await
+            }
+            Target::PullRequestCommits => {
+                self.run(target, output, repos, |s| {
+                    let fetcher = PullFileFetcher::new(s.owner, s.name, s.since, octocrab.clone())
+                        .with_skip_errors(self.skip_errors);
+                    records!(fetcher.with_cursor(s.cursor), |f| f.stream_commits())
+                })
+                .await
+            }
+            Target::Labels => {
+                self.run(target, output, repos, |s| {
+                    let fetcher = LabelFetcher::new(s.owner, s.name, octocrab.clone());
+                    records!(fetcher.with_cursor(s.cursor), |f| f.stream())
+                })
+                .await
+            }
+            Target::Milestones => {
+                self.run(target, output, repos, |s| {
+                    let fetcher = MilestoneFetcher::new(s.owner, s.name, octocrab.clone());
+                    records!(fetcher.with_cursor(s.cursor), |f| f.stream())
+                })
+                .await
+            }
+            Target::Branches => {
+                self.run(target, output, repos, |s| {
+                    let fetcher = BranchFetcher::new(s.owner, s.name, octocrab.clone());
+                    records!(fetcher.with_cursor(s.cursor), |f| f.stream())
+                })
+                .await
+            }
+            Target::Tags => {
+                self.run(target, output, repos, |s| {
+                    let fetcher = TagFetcher::new(s.owner, s.name, octocrab.clone());
+                    records!(fetcher.with_cursor(s.cursor), |f| f.stream())
+                })
+                .await
+            }
+            Target::BranchProtections => {
+                self.run(target, output, repos, |s| {
+                    let fetcher = BranchProtectionFetcher::new(s.owner, s.name, octocrab.clone())
+                        .with_skip_errors(self.skip_errors);
+                    records!(fetcher.with_cursor(s.cursor), |f| f.stream())
+                })
+                .await
+            }
+            Target::Releases => {
+                self.run(target, output, repos, |s| {
+                    let fetcher = ReleaseFetcher::new(s.owner, s.name, octocrab.clone());
+                    records!(fetcher.with_cursor(s.cursor), |f| f.stream())
+                })
+                .await
+            }
+            Target::Workflows => {
+                self.run(target, output, repos, |s| {
+                    let fetcher = WorkFlowFetcher::new(s.owner, s.name, octocrab.clone());
+                    records!(fetcher.with_cursor(s.cursor), |f| f.stream())
+                })
+                .await
+            }
+            Target::Runs => {
+                self.run(target, output, repos, |s| {
+                    let fetcher = RunFetcher::new(s.owner, s.name, s.since, octocrab.clone());
+                    let workflow_file = self.workflow_file.clone();
+                    records!(fetcher.with_cursor(s.cursor), |f| f.stream(workflow_file))
+                })
+                .await
+            }
+            Target::Jobs => {
+                self.run(target, output, repos, |s| {
+                    let fetcher = JobFetcher::new(s.owner, s.name, s.since, octocrab.clone())
+                        .with_skip_errors(self.skip_errors);
+                    let run_id = self.run_id.clone();
+                    records!(fetcher.with_cursor(s.cursor), |f| f.stream(run_id))
+                })
+                .await
+            }
+            Target::Deployments => {
+                self.run(target, output, repos, |s| {
+                    let fetcher =
+                        DeploymentFetcher::new(s.owner, s.name, s.since, octocrab.clone());
+                    records!(fetcher.with_cursor(s.cursor), |f| f.stream())
+                })
+                .await
+            }
+            Target::DeploymentStatuses => {
+                self.run(target, output, repos, |s| {
+                    let fetcher =
+                        DeploymentStatusFetcher::new(s.owner, s.name, s.since, octocrab.clone())
+                            .with_skip_errors(self.skip_errors);
+                    records!(fetcher.with_cursor(s.cursor), |f| f.stream())
+                })
+                .await
+            }
+            Target::CheckSuites => {
+                self.run(target, output, repos, |s| {
+                    let fetcher =
+                        CheckSuiteFetcher::new(s.owner, s.name, s.since, octocrab.clone())
+                            .with_skip_errors(self.skip_errors);
+                    records!(fetcher.with_cursor(s.cursor), |f| f.stream())
+                })
+                .await
+            }
+            Target::CheckRuns => {
+                self.run(target, output, repos, |s| {
+                    let fetcher = CheckRunFetcher::new(s.owner, s.name, s.since, octocrab.clone())
+                        .with_skip_errors(self.skip_errors);
+                    records!(fetcher.with_cursor(s.cursor), |f| f.stream())
+                })
+                .await
+            }
+            Target::Statuses => {
+                self.run(target, output, repos, |s| {
+                    let fetcher = StatusFetcher::new(s.owner, s.name, s.since, octocrab.clone())
+                        .with_skip_errors(self.skip_errors);
+                    records!(fetcher.with_cursor(s.cursor), |f| f.stream())
+                })
+                .await
+            }
+            Target::Reviews => {
+                self.run(target, output, repos, |s| {
+                    let fetcher = ReviewFetcher::new(s.owner, s.name, s.since, octocrab.clone())
+                        .with_skip_errors(self.skip_errors);
+                    records!(fetcher.with_cursor(s.cursor), |f| f.stream())
+                })
+                .await
+            }
+            Target::ReviewComments => {
+                self.run(target, output, repos, |s| {
+                    let fetcher =
+                        ReviewCommentFetcher::new(s.owner, s.name, s.since, octocrab.clone());
+                    records!(fetcher.with_cursor(s.cursor), |f| f.stream())
+                })
+                .await
+            }
+            Target::UsersDetailed => {
+                self.run(target, output, repos, |s| {
+                    let fetcher = UserDetailedFetcher::new(octocrab.clone());
+                    records!(fetcher.with_cursor(s.cursor), |f| f.stream())
+                })
+                .await
+            }
+            Target::Users => {
+                self.run(target, output, repos, |s| {
+                    let fetcher = UserFetcher::new(octocrab.clone());
+                    records!(fetcher.with_cursor(s.cursor), |f| f.stream())
+                })
+                .await
+            }
+        }
+    }
+
+    // Extracts `target` from every repository into one sink, with `fetch`
+    // building the record stream of each repository.
+    async fn run<R, S, F>(
+        &self,
+        target: Target,
+        output: &Output,
+        repos: &[Repo],
+        fetch: F,
+    ) -> Result<()>
+    where
+        R: Serialize + Table + 'static,
+        S: Stream<Item = octx::Result<R>>,
+        F: Fn(Scope) -> S,
+    {
+        if self.concurrency > 1 {
+            let mut sink = output.sink::<R>()?;
+            self.run_concurrently(target, repos, &mut sink, fetch)
+                .await?;
+            return sink.close();
+        }
+        let mut sink = output.sink::<R>()?;
+        let mut checkpoint = output.resume.clone().unwrap_or_default();
+        // The repository the interrupted extraction stopped at
+        let mut resume_at = output.resume.as_ref().map(|c| c.repository.clone());
+        for repo in repos {
+            let repository = repo.full_name();
+            let cursor = match resume_at.take() {
+                Some(Some(stopped_at)) if stopped_at != repository => {
+                    resume_at = Some(Some(stopped_at));
+                    continue;
+                }
+                Some(_) => Cursor::starting_at(checkpoint.page.clone()),
+                None => {
+                    checkpoint = Checkpoint {
+                        repository: Some(repository.clone()),
+                        records: checkpoint.records,
+                        ..Default::default()
+                    };
+                    Cursor::default()
+                }
+            };
+            let since = self.since_of(target, &repository)?;
+
+            let started_at = Utc::now();
+            let records = fetch(Scope {
+                owner: repo.owner.clone(),
+                name: repo.name.clone(),
+                since,
+                cursor: cursor.clone(),
+            });
+            let mut repo_sink = WatermarkSink::new(&mut sink);
+            match &output.checkpoint {
+                Some(path) => {
+                    let save = |checkpoint: &Checkpoint| output.save_checkpoint(path, checkpoint);
+                    write_resumable(records, &mut repo_sink, &cursor, &mut checkpoint, save)
+                        .await?;
+                }
+                None => write_stream(records, &mut repo_sink).await?,
+            }
+
+            // Records without a watermark of their own are covered by the time
+            // the extraction started, as anything updated later is listed next time
+            if let (Some(state), true) = (&self.state, target.incremental()) {
+                repo_sink.flush()?;
+                let watermark = repo_sink.latest().unwrap_or(started_at);
+                state.save(&repository, target.name(), watermark)?;
+            }
+        }
+        if let Some(Some(stopped_at)) = resume_at {
+            bail!("{} to resume from is no longer extracted", stopped_at);
+        }
+
+        sink.close()?;
+        if let Some(path) = &output.checkpoint {
+            info!("Extracted {} records", checkpoint.records);
+            // Nothing is saved before the first record of an empty listing
+            match fs::remove_file(path) {
+                Err(e) if e.kind() != io::ErrorKind::NotFound => return Err(e.into()),
+                _ => {}
+            }
+        }
+        Ok(())
+    }
+
+    // Same as `run` without checkpoints, extracting `concurrency` repositories
+    // at a time into `sink`. Records are written whole as they arrive, so
+    // those of different repositories are mixed in the output.
+    async fn run_concurrently<R, K, S, F>(
+        &self,
+        target: Target,
+        repos: &[Repo],
+        sink: &mut K,
+        fetch: F,
+    ) -> Result<()>
+    where
+        R: Serialize + Table + 'static,
+        K: RecordSink<R> + ?Sized,
+        S: Stream<Item = octx::Result<R>>,
+        F: Fn(Scope) -> S,
+    {
+        let mut streams = vec![];
+        for (i, repo) in repos.iter().enumerate() {
+            let records = fetch(Scope {
+                owner: repo.owner.clone(),
+                name: repo.name.clone(),
+                since: self.since_of(target, &repo.full_name())?,
+                cursor: Cursor::default(),
+            });
+            let done = stream::once(async move { Ok(Fetched::Done(i)) });
+            let fetched = records.map_ok(move |record| Fetched::Record(i, record));
+            streams.push(fetched.chain(done).boxed_local());
+        }
+
+        let started_at = Utc::now();
+        let mut latest = vec![None; repos.len()];
+        let fetched = stream::iter(streams).flatten_unordered(self.concurrency);
+        pin_mut!(fetched);
+        while let Some(fetched) = fetched.try_next().await? {
+            match fetched {
+                Fetched::Record(i, record) => {
+                    latest[i] = latest[i].max(record.watermark());
+                    sink.write(record)?;
+                }
+                Fetched::Done(i) => {
+                    if let (Some(state), true) = (&self.state, target.incremental()) {
+                        sink.flush()?;
+                        let watermark = latest[i].unwrap_or(started_at);
+                        state.save(&repos[i].full_name(), target.name(), watermark)?;
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+
+    // --days-ago or --since-date, or else the state of the repository
+    fn since_of(&self, target: Target, repository: &str) -> Result<Option<DateTime<Utc>>> {
+        let since = match (&self.state, self.since) {
+            (_, Some(since)) => Some(since),
+            (Some(state), None) if target.incremental() => state.load(repository, target.name())?,
+            _ => None,
+        };
+        info!(
+            "Target: {} of {} since {:?}",
+            target.name(),
+            repository,
+            since
+        );
+        Ok(since)
+    }
+}
+
+// A record of the repository at the index, or the end of its records
+enum Fetched<R> {
+    Record(usize, R),
+    Done(usize),
+}
+
+fn client(env: Env, retry: RetryPolicy) -> Result<octocrab::Octocrab> {
+    let url = env.github_api_url.context("GITHUB_API_URL is required")?;
+    let credentials = match (
+        env.github_api_token,
+        env.github_app_id,
+        env.github_app_private_key_path,
+        env.github_app_installation_id,
     ) {
-        (Some(token), None, None, None) => builder.personal_token(token).build()?,
+        (Some(token), None, None, None) => Credentials::Token(token),
         (None, Some(app_id), Some(key_path), Some(installation_id)) => {
-            let pem = std::fs::read(&key_path)
-                .with_context(|| format!("while reading GITHUB_APP_PRIVATE_KEY_PATH={}", key_path))
-                .unwrap();
+            let pem = std::fs::read(&key_path).with_context(|| {
+                format!("while reading GITHUB_APP_PRIVATE_KEY_PATH={}", key_path)
+            })?;
             let key = jsonwebtoken::EncodingKey::from_rsa_pem(&pem)
-                .context("while parsing the private key file as RSA PEM")
-                .unwrap();
-            builder
-                .app(octocrab::models::AppId(app_id), key)
-                .build()?
-                .installation(octocrab::models::InstallationId(installation_id))?
-        }
-        _ => panic!(
+                .context("while parsing the private key file as RSA PEM")?;
+            Credentials::App {
+                app_id,
+                key,
+                installation_id,
+            }
+        }
+        _ => bail!(
             "set either GITHUB_API_TOKEN or all of \
              GITHUB_APP_ID, GITHUB_APP_PRIVATE_KEY_PATH, GITHUB_APP_INSTALLATION_ID"
         ),
     };
+    octx::client::build(&url, credentials, RateLimiter::new(), retry)
+}
+
+async fn org_repos(
+    octocrab: &octocrab::Octocrab,
+    org: &str,
+    filter: RepoFilter,
+) -> Result<Vec<Repo>> {
+    let runner = OrgRepoFetcher::new(org.to_string(), filter, octocrab.clone());
+    let repos = runner
+        .stream()
+        .map_ok(|repo| Repo {
+            owner: repo.owner().to_string(),
+            name: repo.name,
+        })
+        .try_collect::<Vec<Repo>>()
+        .await?;
+    info!("{} repositories in {}", repos.len(), org);
+    Ok(repos)
+}
 
-    let wtr = WriterBuilder::new()
-        .has_headers(true)
-        .from_writer(io::stdout());
-
-    if args.target_users_detailed {
-        info!("Target: users(detailed)");
-        let runner = UserDetailedFetcher::new(octocrab);
-        runner.fetch(wtr).await?;
-    } else if args.target_users {
-        info!("Target: users");
-        let runner = UserFetcher::new(octocrab);
-        runner.fetch(wtr).await?;
+fn since(days_ago: Option<i64>, since_date: Option<&str>) -> Result<Option<DateTime<Utc>>> {
+    if let Some(ago) = days_ago {
+        Ok(Some(Utc::now() - Duration::days(ago)))
+    } else if let Some(date) = since_date {
+        let since = DateTime::parse_from_rfc3339(date)
+            .with_context(|| format!("while parsing since date {}", date))?;
+        Ok(Some(since.into()))
     } else {
-        let owner = args.owner.unwrap();
-        let name = args.name.unwrap();
-
-        let since = if args.days_ago.is_some() {
-            args.days_ago.map(|ago| Utc::now() - Duration::days(ago))
-        } else if args.since_date.is_some() {
-            args.since_date
-                .map(|date| DateTime::parse_from_rfc3339(&date).unwrap().into())
-        } else {
-            None
+        Ok(None)
+    }
+}
+
+// `octx run --config`: every job shares the client and the state directory
+async fn run(path: &PathBuf) -> Result<()> {
+    let config =
+        Config::load(path).with_context(|| format!("while reading config {}", path.display()))?;
+    let mut env: Env = envy::from_env().context("while reading from environment")?;
+    if config.api_url.is_some() {
+        env.github_api_url = config.api_url.clone();
+    }
+    if !config.auth.is_empty() {
+        env.github_api_token = config.auth.token()?;
+        env.github_app_id = config.auth.app_id;
+        env.github_app_private_key_path = config.auth.private_key_path.clone();
+        env.github_app_installation_id = config.auth.installation_id;
+    }
+    let octocrab = client(env, config.retry.policy())?;
+
+    #[cfg(not(feature = "sqlite"))]
+    if config.output.sqlite.is_some() {
+        bail!("sqlite output needs octx built with the `sqlite` feature");
+    }
+    #[cfg(not(feature = "postgres"))]
+    if config.output.postgres.is_some() {
+        bail!("postgres output needs octx built with the `postgres` feature");
+    }
+
+    for (i, job) in config.jobs.iter().enumerate() {
+        let targets = job
+            .targets
+            .iter()
+            .map(|target| target.parse::<Target>().map_err(Error::msg))
+            .collect::<Result<Vec<Target>>>()
+            .with_context(|| format!("in job {}", i + 1))?;
+        let output = Output {
+            format: config.output.format,
+            path: None,
+            out_dir: job.out_dir(&config.output).map(PathBuf::from),
+            checkpoint: None,
+            resume: None,
+            #[cfg(feature = "sqlite")]
+            sqlite: config.output.sqlite.clone(),
+            #[cfg(feature = "postgres")]
+            postgres: config.output.postgres.clone(),
         };
+        output.check_targets(&targets)?;
 
-        if args.target_issues {
-            info!("Target: issues");
-            let runner = IssueFetcher::new(owner, name, since, octocrab);
-            runner.fetch(wtr).await?;
-        } else if args.target_events {
-            info!("Target: events");
-            let runner = IssueEventFetcher::new(owner, name, since, octocrab);
-            runner.fetch(wtr).await?;
-        } else if args.target_comments {
-            info!("Target: comments");
-            let runner = CommentFetcher::new(owner, name, since, octocrab);
-            runner.fetch(wtr).await?;
-        } else if args.target_commits {
-            info!("Target: commits");
-            let runner = CommitFetcher::new(owner, name, since, octocrab);
-            runner.fetch(wtr).await?;
-        } else if args.target_pull_files {
-            info!("Target: pull files");
-            let runner = PullFileFetcher::new(owner, name, since, octocrab);
-            runner.fetch(wtr).await?;
-        } else if args.target_pull_commits {
-            info!("Target: pull commits");
-            let runner = PullFileFetcher::new(owner, name, since, octocrab);
-            runner.fetch_commits(wtr).await?;
-        } else if args.target_labels {
-            info!("Target: labels");
-            let runner = LabelFetcher::new(owner, name, octocrab);
-            runner.fetch(wtr).await?;
-        } else if args.target_releases {
-            info!("Target: releases");
-            let runner = ReleaseFetcher::new(owner, name, octocrab);
-            runner.fetch(wtr).await?;
-        } else if args.target_workflows {
-            info!("Target: workflows");
-            let runner = WorkFlowFetcher::new(owner, name, octocrab);
-            runner.fetch(wtr).await?;
-        } else if args.target_runs {
-            info!("Target: runs");
-            let runner = RunFetcher::new(owner, name, since, octocrab);
-            runner.fetch(wtr, args.workflow_file).await?;
-        } else if args.target_jobs {
-            info!("Target: jobs");
-            let runner = JobFetcher::new(owner, name, since, octocrab);
-            runner.fetch(wtr, args.run_id).await?;
-        } else if args.target_reviews {
-            info!("Target: reviews");
-            let runner = ReviewFetcher::new(owner, name, since, octocrab);
-            runner.fetch(wtr).await?;
-        } else {
-            error!("No target specified");
-        }
+        info!("Job {}: {} targets", i + 1, targets.len());
+        let repos = match &job.org {
+            Some(org) => {
+                let filter = RepoFilter {
+                    skip_archived: job.skip_archived,
+                    skip_forks: job.skip_forks,
+                    visibility: job.visibility.clone(),
+                };
+                org_repos(&octocrab, org, filter).await?
+            }
+            None => job
+                .repositories
+                .iter()
+                .map(|repository| repository.parse())
+                .collect::<Result<Vec<Repo>>>()?,
+        };
+        let extraction = Extraction {
+            octocrab: octocrab.clone(),
+            output,
+            state: config.state_dir.clone().map(StateDir::new),
+            since: since(job.days_ago, job.since_date.as_deref())?,
+            workflow_file: None,
+            run_id: None,
+            concurrency: config.concurrency.unwrap_or(1),
+            skip_errors: config.skip_errors,
+        };
+        extraction.extract_targets(&targets, &repos).await?;
     }
     Ok(())
 }
+
+#[tokio::main]
+async fn main() -> Result<()> {
+    // Warnings such as waiting for the rate limit are shown unless RUST_LOG says otherwise
+    env_logger::Builder::from_env(env_logger::Env::default().default_filter_or("warn")).init();
+    let args: Command = Command::from_args();
+    match &args.subcommand {
+        Some(Subcommand::Schema { format, targets }) => {
+            let schemas = targets
+                .iter()
+                .map(|target| target.schema(*format))
+                .collect::<Vec<String>>();
+            print!("{}", schemas.join("\n"));
+            return Ok(());
+        }
+        Some(Subcommand::Run { config }) => return run(config).await,
+        None => {}
+    }
+
+    let env: Env = envy::from_env().context("while reading from environment")?;
+    if args.max_attempts == 0 {
+        bail!("--max-attempts must be at least 1");
+    }
+    let retry = RetryPolicy {
+        max_attempts: args.max_attempts,
+        max_elapsed: std::time::Duration::from_secs(args.max_retry_secs),
+        ..Default::default()
+    };
+    let octocrab = client(env, retry)?;
+
+    let resume = if args.resume {
+        let path = args
+            .checkpoint
+            .as_ref()
+            .context("--resume needs --checkpoint")?;
+        let checkpoint = Checkpoint::load(path)
+            .with_context(|| format!("while reading checkpoint {}", path.display()))?;
+        info!(
+            "Resuming after {} records from {:?} of {:?}",
+            checkpoint.records, checkpoint.page, checkpoint.repository
+        );
+        Some(checkpoint)
+    } else {
+        None
+    };
+    let output = Output {
+        format: args.format,
+        path: args.output.clone(),
+        out_dir: args.out_dir.clone(),
+        checkpoint: args.checkpoint.clone(),
+        resume,
+        #[cfg(feature = "sqlite")]
+        sqlite: args.sqlite.clone(),
+        #[cfg(feature = "postgres")]
+        postgres: args.postgres.clone(),
+    };
+
+    if args.concurrency == 0 {
+        bail!("--concurrency must be at least 1");
+    }
+    if args.concurrency > 1 && args.checkpoint.is_some() {
+        bail!("--checkpoint needs repositories to be extracted one at a time");
+    }
+
+    let targets = args.targets();
+    if targets.is_empty() {
+        error!("No target specified");
+        return Ok(());
+    }
+    output.check_targets(&targets)?;
+
+    let repos = match &args.org {
+        _ if targets.iter().all(Target::global) => vec![],
+        Some(org) => {
+            let filter = RepoFilter {
+                skip_archived: args.skip_archived,
+                skip_forks: args.skip_forks,
+                visibility: args.visibility.clone(),
+            };
+            org_repos(&octocrab, org, filter).await?
+        }
+        None => vec![Repo {
+            owner: args.owner.clone().context("owner is required")?,
+            name: args.name.clone().context("name is required")?,
+        }],
+    };
+
+    let extraction = Extraction {
+        octocrab,
+        output,
+        state: args.state_dir.map(StateDir::new),
+        since: since(args.days_ago, args.since_date.as_deref())?,
+        workflow_file: args.workflow_file,
+        run_id: args.run_id,
+        concurrency: args.concurrency,
+        skip_errors: args.skip_errors,
+    };
+    extraction.extract_targets(&targets, &repos).await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+    use octx::schema::Column;
+    #[cfg(feature = "sqlite")]
+    use octx::schema::{create_table_sql, quote_idents, upsert_sql, ColumnType, Dialect};
+    #[cfg(feature = "sqlite")]
+    use octx::sqlite_sink::SqliteSink;
+    #[cfg(feature = "sqlite")]
+    use rusqlite::types::Value;
+    #[cfg(feature = "sqlite")]
+    use rusqlite::{params_from_iter, Connection};
+
+    // A record of the `n`th item of the `repo`th repository
+    #[derive(Serialize, Debug, Clone, PartialEq)]
+    struct Rec {
+        repo: usize,
+        n: u32,
+        updated_at: DateTime<Utc>,
+    }
+
+    impl Table for Rec {
+        fn table_name() -> &'static str {
+            "recs"
+        }
+
+        fn columns() -> Vec<Column> {
+            vec![
+                Column::of::<u64>("repo", "usize"),
+                Column::of::<u32>("n", "u32"),
+                Column::of::<DateTime<Utc>>("updated_at", "DateTime<Utc>"),
+            ]
+        }
+
+        fn watermark(&self) -> Option<DateTime<Utc>> {
+            Some(self.updated_at)
+        }
+    }
+
+    fn temp_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("octx-test-{}-{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    fn extraction(output: Output, state: Option<StateDir>, concurrency: usize) -> Extraction {
+        Extraction {
+            octocrab: octocrab::Octocrab::default(),
+            output,
+            state,
+            since: None,
+            workflow_file: None,
+            run_id: None,
+            concurrency,
+            skip_errors: false,
+        }
+    }
+
+    fn file_output(path: PathBuf, checkpoint: Option<PathBuf>) -> Output {
+        Output {
+            format: OutputFormat::Csv,
+            path: Some(path),
+            out_dir: None,
+            checkpoint,
+            resume: None,
+            #[cfg(feature = "sqlite")]
+            sqlite: None,
+            #[cfg(feature = "postgres")]
+            postgres: None,
+        }
+    }
+
+    fn repo(name: &str) -> Repo {
+        Repo {
+            owner: "acme".to_string(),
+            name: name.to_string(),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_checkpoint_of_empty_listing() -> Result<()> {
+        let dir = temp_dir("empty-listing");
+        let (out, checkpoint) = (dir.join("out.csv"), dir.join("cp.json"));
+        let output = file_output(out.clone(), Some(checkpoint.clone()));
+        let extraction = extraction(output.clone(), None, 1);
+
+        extraction
+            .run(Target::Issues, &output, &[repo("app")], |_| {
+                stream::empty::<octx::Result<Rec>>()
+            })
+            .await?;
+        assert!(out.exists());
+        assert!(!checkpoint.exists());
+
+        fs::remove_dir_all(dir)?;
+        Ok(())
+    }
+
+    // Writes the `n`th record of repository `name` after `ms` milliseconds
+    async fn record(name: &str, n: i64, ms: u64) -> Rec {
+        tokio::time::sleep(std::time::Duration::from_millis(ms)).await;
+        let repo = (name.as_bytes()[0] - b'a') as usize;
+        Rec {
+            repo,
+            n: n as u32,
+            updated_at: Utc
+                .timestamp_opt(1_600_000_000 + repo as i64 * 10 + n, 0)
+                .unwrap(),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_run_concurrently() -> Result<()> {
+        let dir = temp_dir("concurrently");
+        let state = StateDir::new(dir.join("state"));
+        let output = file_output(dir.join("out.csv"), None);
+        let extraction = extraction(output, Some(StateDir::new(dir.join("state"))), 2);
+
+        // a is done while b waits between its records
+        let mut sink: Vec<Rec> = vec![];
+        let fetch = |scope: Scope| {
+            let state = &state;
+            try_stream! {
+                if scope.name == "a" {
+                    for n in 0..3 {
+                        yield record("a", n, 10).await;
+                    }
+                } else {
+                    yield record("b", 0, 0).await;
+                    tokio::time::sleep(std::time::Duration::from_millis(100)).await;
+                    assert!(state.load("acme/a", "issues").unwrap().is_some());
+                    assert!(state.load("acme/b", "issues").unwrap().is_none());
+                    yield record("b", 1, 0).await;
+                }
+            }
+        };
+        let repos = [repo("a"), repo("b")];
+        extraction
+            .run_concurrently(Target::Issues, &repos, &mut sink, fetch)
+            .await?;
+
+        let repos_written = sink.iter().map(|r| r.repo).collect::<Vec<usize>>();
+        assert_eq!(repos_written, vec![1, 0, 0, 0, 1]);
+        sink.sort_by_key(|r| (r.repo, r.n));
+        let mut expected = vec![];
+        for (name, n) in [("a", 0), ("a", 1), ("a", 2), ("b", 0), ("b", 1)].iter() {
+            expected.push(record(name, *n, 0).await);
+        }
+        assert_eq!(sink, expected);
+        assert_eq!(
+            state.load("acme/a", "issues")?,
+            Some(expected[2].updated_at)
+        );
+        assert_eq!(
+            state.load("acme/b", "issues")?,
+            Some(expected[4].updated_at)
+        );
+
+        fs::remove_dir_all(dir)?;
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_run_concurrently_stops_at_error() -> Result<()> {
+        let dir = temp_dir("concurrently-error");
+        let state = StateDir::new(dir.join("state"));
+        let output = file_output(dir.join("out.csv"), None);
+        let extraction = extraction(output, Some(StateDir::new(dir.join("state"))), 2);
+
+        // c starts once a is done, and is still waiting when b fails
+        let mut sink: Vec<Rec> = vec![];
+        let fetch = |scope: Scope| {
+            try_stream! {
+                match scope.name.as_str() {
+                    "a" => yield record("a", 0, 0).await,
+                    "b" => {
+                        yield record("b", 0, 0).await;
+                        tokio::time::sleep(std::time::Duration::from_millis(50)).await;
+                        Err(octx::Error::Sink(anyhow!("b failed")))?;
+                    }
+                    _ => yield record("c", 0, 200).await,
+                }
+            }
+        };
+        let repos = [repo("a"), repo("b"), repo("c")];
+        let result = extraction
+            .run_concurrently(Target::Issues, &repos, &mut sink, fetch)
+            .await;
+
+        assert!(result.is_err());
+        assert_eq!(sink.len(), 2);
+        assert!(state.load("acme/a", "issues")?.is_some());
+        assert!(state.load("acme/b", "issues")?.is_none());
+        assert!(state.load("acme/c", "issues")?.is_none());
+
+        fs::remove_dir_all(dir)?;
+        Ok(())
+    }
+
+    // The option's help lists the targets taking `since` by hand
+    #[test]
+    fn test_days_ago_help_lists_incremental_targets() {
+        let mut help = vec![];
+        Command::clap()
+            .set_term_width(10000)
+            .write_help(&mut help)
+            .unwrap();
+        let help = String::from_utf8(help).unwrap();
+        let days_ago = help.lines().find(|l| l.contains("--days-ago <")).unwrap();
+        let listed = days_ago
+            .split_whitespace()
+            .filter_map(|word| word.strip_prefix("--"))
+            .map(|option| option.trim_end_matches(','))
+            .collect::<Vec<&str>>();
+        for target in Target::ALL.iter() {
+            let name = target.name();
+            assert_eq!(listed.contains(&name), target.incremental(), "{}", name);
+        }
+    }
+
+    // Creates the table of a record type and upserts a row into it twice, and
+    // adds its columns to a table having only the primary key
+    #[cfg(feature = "sqlite")]
+    struct SqliteRoundTrip;
+
+    #[cfg(feature = "sqlite")]
+    impl WithTable for SqliteRoundTrip {
+        type Output = anyhow::Result<()>;
+
+        fn call<R: Table>(self) -> anyhow::Result<()> {
+            let conn = Connection::open_in_memory()?;
+            conn.execute_batch(&create_table_sql::<R>(Dialect::Sqlite))?;
+            let row = R::columns()
+                .iter()
+                .map(|c| match c.ty {
+                    ColumnType::Text | ColumnType::Timestamp | ColumnType::Json => {
+                        Value::Text("x".to_string())
+                    }
+                    _ => Value::Integer(0),
+                })
+                .collect::<Vec<Value>>();
+            for _ in 0..2 {
+                conn.execute(&upsert_sql::<R>(), params_from_iter(row.iter()))?;
+            }
+
+            let conn = Connection::open_in_memory()?;
+            conn.execute_batch(&format!(
+                "CREATE TABLE \"{}\" ({})",
+                R::table_name(),
+                quote_idents(R::primary_key())
+            ))?;
+            SqliteSink::new::<R>(conn)?;
+            Ok(())
+        }
+    }
+
+    #[cfg(feature = "sqlite")]
+    #[test]
+    fn test_sqlite_tables_of_every_target() {
+        for target in Target::ALL.iter() {
+            target
+                .with_table(SqliteRoundTrip)
+                .unwrap_or_else(|e| panic!("{}: {:#}", target.name(), e));
+        }
+    }
+}
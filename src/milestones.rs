@@ -0,0 +1,162 @@
+use octocrab::models::*;
+use serde::*;
+use url::Url;
+
+use async_stream::try_stream;
+use futures::{pin_mut, Stream, TryStreamExt};
+
+use crate::checkpoint::Cursor;
+use crate::*;
+
+type DateTime = chrono::DateTime<chrono::Utc>;
+
+record! {
+    #[table(name = "milestones")]
+    #[derive(Serialize, Debug)]
+    pub struct MilestoneRec {
+        pub id: i64,
+        pub node_id: String,
+        pub url: Url,
+        pub html_url: Url,
+        pub number: i64,
+        pub title: String,
+        pub description: Option<String>,
+        pub state: Option<String>,
+        pub open_issues: Option<i64>,
+        pub closed_issues: Option<i64>,
+        pub creator_id: Option<i64>,
+        pub due_on: Option<DateTime>,
+        pub created_at: DateTime,
+        pub updated_at: Option<DateTime>,
+        pub closed_at: Option<DateTime>,
+
+        pub sdc_repository: String,
+    }
+}
+
+impl RepositryAware for MilestoneRec {
+    fn set_repository(&mut self, name: String) {
+        self.sdc_repository = name;
+    }
+}
+
+impl From<Milestone> for MilestoneRec {
+    fn from(from: Milestone) -> Self {
+        Self {
+            id: from.id.0 as i64,
+            node_id: from.node_id,
+            url: from.url,
+            html_url: from.html_url,
+            number: from.number,
+            title: from.title,
+            description: from.description,
+            state: from.state,
+            open_issues: from.open_issues,
+            closed_issues: from.closed_issues,
+            creator_id: from.creator.map(|u| u.id.0 as i64),
+            due_on: from.due_on,
+            created_at: from.created_at,
+            updated_at: from.updated_at,
+            closed_at: from.closed_at,
+
+            sdc_repository: String::default(),
+        }
+    }
+}
+
+pub struct MilestoneFetcher {
+    owner: String,
+    name: String,
+    octocrab: octocrab::Octocrab,
+    cursor: Cursor,
+}
+
+impl MilestoneFetcher {
+    pub fn new(owner: String, name: String, octocrab: octocrab::Octocrab) -> Self {
+        Self {
+            owner,
+            name,
+            octocrab,
+            cursor: Cursor::default(),
+        }
+    }
+
+    pub fn with_cursor(mut self, cursor: Cursor) -> Self {
+        self.cursor = cursor;
+        self
+    }
+}
+
+impl UrlConstructor for MilestoneFetcher {
+    fn reponame(&self) -> String {
+        format!("{}/{}", self.owner, self.name)
+    }
+
+    fn entrypoint_route(&self) -> crate::Result<String> {
+        // Only open milestones are listed by default
+        let param = Params {
+            state: octocrab::params::State::All.into(),
+            ..Default::default()
+        };
+
+        Ok(format!(
+            "/repos/{owner}/{repo}/milestones?{query}",
+            owner = &self.owner,
+            repo = &self.name,
+            query = param.to_query()?,
+        ))
+    }
+}
+
+impl LoopWriter for MilestoneFetcher {
+    type Model = Milestone;
+    type Record = MilestoneRec;
+}
+
+impl MilestoneFetcher {
+    pub fn stream(&self) -> impl Stream<Item = crate::Result<MilestoneRec>> + '_ {
+        try_stream! {
+            let route = self.entrypoint_route()?;
+            let pages = self.cursor.pages::<Milestone>(&self.octocrab, route);
+            pin_mut!(pages);
+            while let Some(mut page) = pages.try_next().await? {
+                for record in self.take_records(&mut page) {
+                    yield record;
+                }
+            }
+        }
+    }
+
+    pub async fn fetch<S: RecordSink<MilestoneRec> + ?Sized>(
+        &self,
+        sink: &mut S,
+    ) -> crate::Result<()> {
+        write_stream(self.stream(), sink).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_convert_milestone_model() -> Result<(), Box<dyn std::error::Error>> {
+        let issue: serde_json::Value =
+            serde_json::from_str(include_str!("../testdata/issue.json"))?;
+        let model: Milestone = serde_json::from_value(issue["milestone"].clone())?;
+
+        let record: MilestoneRec = model.into();
+
+        assert_eq!(record.id, 1002604);
+        assert_eq!(record.number, 1);
+        assert_eq!(record.title, "v1.0".to_string());
+        assert_eq!(record.state, Some("open".to_string()));
+        assert_eq!(record.open_issues, Some(4));
+        assert_eq!(record.closed_issues, Some(8));
+        assert_eq!(record.creator_id, Some(1));
+        assert!(record.due_on.is_some());
+        assert!(record.closed_at.is_some());
+
+        Ok(())
+    }
+}
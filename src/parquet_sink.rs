@@ -0,0 +1,199 @@
+use std::io::Write;
+use std::sync::Arc;
+
+use arrow_array::{
+    ArrayRef, BooleanArray, Int32Array, Int64Array, RecordBatch, StringArray,
+    TimestampMicrosecondArray, UInt32Array, UInt64Array,
+};
+use arrow_schema::{DataType, Field, Schema, SchemaRef, TimeUnit};
+use parquet::arrow::ArrowWriter;
+use parquet::basic::Compression;
+use parquet::file::properties::WriterProperties;
+use serde::Serialize;
+use serde_json::{Map, Value};
+
+use crate::schema::{Column, ColumnType, Table};
+use crate::RecordSink;
+
+pub fn arrow_schema(columns: &[Column]) -> Schema {
+    let fields = columns
+        .iter()
+        .map(|c| Field::new(c.name, data_type(c.ty), c.nullable))
+        .collect::<Vec<Field>>();
+    Schema::new(fields)
+}
+
+fn data_type(ty: ColumnType) -> DataType {
+    match ty {
+        ColumnType::Boolean => DataType::Boolean,
+        ColumnType::Int32 => DataType::Int32,
+        ColumnType::Int64 => DataType::Int64,
+        ColumnType::UInt32 => DataType::UInt32,
+        ColumnType::UInt64 => DataType::UInt64,
+        ColumnType::Text | ColumnType::Json => DataType::Utf8,
+        ColumnType::Timestamp => DataType::Timestamp(TimeUnit::Microsecond, Some("UTC".into())),
+    }
+}
+
+// Writes records into a Parquet file typed after the record's `Table` columns.
+// Records are buffered and handed to the writer every `batch_size` rows
+// (one API page by default), so memory stays bounded on long extractions.
+pub struct ParquetSink<W: Write + Send> {
+    writer: ArrowWriter<W>,
+    schema: SchemaRef,
+    columns: Vec<Column>,
+    rows: Vec<Map<String, Value>>,
+    batch_size: usize,
+}
+
+impl<W: Write + Send> ParquetSink<W> {
+    pub fn new<R: Table>(inner: W) -> anyhow::Result<Self> {
+        let columns = R::columns();
+        let schema = Arc::new(arrow_schema(&columns));
+        let props = WriterProperties::builder()
+            .set_compression(Compression::SNAPPY)
+            .build();
+        let writer = ArrowWriter::try_new(inner, schema.clone(), Some(props))?;
+        Ok(Self {
+            writer,
+            schema,
+            columns,
+            rows: vec![],
+            batch_size: 100,
+        })
+    }
+
+    pub fn batch_size(mut self, batch_size: usize) -> Self {
+        self.batch_size = batch_size;
+        self
+    }
+
+    fn write_batch(&mut self) -> anyhow::Result<()> {
+        if self.rows.is_empty() {
+            return Ok(());
+        }
+        let arrays = self
+            .columns
+            .iter()
+            .map(|c| build_array(c, &self.rows))
+            .collect::<anyhow::Result<Vec<ArrayRef>>>()?;
+        let batch = RecordBatch::try_new(self.schema.clone(), arrays)?;
+        self.writer.write(&batch)?;
+        self.rows.clear();
+        Ok(())
+    }
+}
+
+impl<R: Serialize, W: Write + Send> RecordSink<R> for ParquetSink<W> {
+    fn write(&mut self, record: R) -> anyhow::Result<()> {
+        match serde_json::to_value(&record)? {
+            Value::Object(row) => self.rows.push(row),
+            _ => anyhow::bail!("record must be a struct"),
+        }
+        if self.rows.len() >= self.batch_size {
+            self.write_batch()?;
+        }
+        Ok(())
+    }
+
+    fn flush(&mut self) -> anyhow::Result<()> {
+        self.write_batch()?;
+        self.writer.flush()?;
+        Ok(())
+    }
+
+    fn close(&mut self) -> anyhow::Result<()> {
+        self.write_batch()?;
+        self.writer.finish()?;
+        Ok(())
+    }
+}
+
+fn build_array(column: &Column, rows: &[Map<String, Value>]) -> anyhow::Result<ArrayRef> {
+    let values = rows
+        .iter()
+        .map(|row| row.get(column.name).unwrap_or(&Value::Null));
+    let array: ArrayRef = match column.ty {
+        ColumnType::Boolean => Arc::new(values.map(Value::as_bool).collect::<BooleanArray>()),
+        ColumnType::Int32 => Arc::new(
+            values
+                .map(|v| v.as_i64().map(|n| n as i32))
+                .collect::<Int32Array>(),
+        ),
+        ColumnType::Int64 => Arc::new(values.map(Value::as_i64).collect::<Int64Array>()),
+        ColumnType::UInt32 => Arc::new(
+            values
+                .map(|v| v.as_u64().map(|n| n as u32))
+                .collect::<UInt32Array>(),
+        ),
+        ColumnType::UInt64 => Arc::new(values.map(Value::as_u64).collect::<UInt64Array>()),
+        ColumnType::Text | ColumnType::Json => Arc::new(
+            values
+                .map(|v| match v {
+                    Value::Null => None,
+                    Value::String(s) => Some(s.clone()),
+                    other => Some(other.to_string()),
+                })
+                .collect::<StringArray>(),
+        ),
+        ColumnType::Timestamp => {
+            let micros = values
+                .map(|v| match v.as_str() {
+                    Some(s) => chrono::DateTime::parse_from_rfc3339(s)
+                        .map(|t| Some(t.timestamp_micros()))
+                        .map_err(|e| {
+                            anyhow::anyhow!("{}: invalid timestamp {:?}: {}", column.name, s, e)
+                        }),
+                    None => Ok(None),
+                })
+                .collect::<anyhow::Result<Vec<Option<i64>>>>()?;
+            Arc::new(TimestampMicrosecondArray::from(micros).with_timezone("UTC"))
+        }
+    };
+    Ok(array)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::issues::IssueRec;
+    use octocrab::models::issues::Issue;
+    use parquet::arrow::arrow_reader::ParquetRecordBatchReaderBuilder;
+
+    #[test]
+    fn test_write_typed_issues() -> Result<(), Box<dyn std::error::Error>> {
+        let path = std::env::temp_dir().join(format!("octx-test-{}.parquet", std::process::id()));
+        {
+            let file = std::fs::File::create(&path)?;
+            let mut sink = ParquetSink::new::<IssueRec>(file)?.batch_size(2);
+            for _ in 0..3 {
+                let model: Issue = serde_json::from_str(include_str!("../testdata/issue.json"))?;
+                let record: IssueRec = model.into();
+                sink.write(record)?;
+            }
+            RecordSink::<IssueRec>::close(&mut sink)?;
+        }
+
+        let reader = ParquetRecordBatchReaderBuilder::try_new(std::fs::File::open(&path)?)?;
+        let schema = reader.schema().clone();
+        assert_eq!(schema.field_with_name("id")?.data_type(), &DataType::Int64);
+        assert_eq!(
+            schema.field_with_name("comments")?.data_type(),
+            &DataType::UInt32
+        );
+        assert_eq!(
+            schema.field_with_name("created_at")?.data_type(),
+            &DataType::Timestamp(TimeUnit::Microsecond, Some("UTC".into()))
+        );
+        assert!(schema.field_with_name("closed_at")?.is_nullable());
+
+        let rows: usize = reader
+            .build()?
+            .map(|b| b.map(|b| b.num_rows()))
+            .sum::<Result<_, _>>()?;
+        assert_eq!(rows, 3);
+
+        std::fs::remove_file(&path)?;
+        Ok(())
+    }
+}
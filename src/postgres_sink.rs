@@ -0,0 +1,282 @@
+use std::io::Write;
+
+use postgres::{Client, NoTls};
+use serde::Serialize;
+use serde_json::{Map, Value};
+
+use crate::schema::{
+    create_table_sql, on_conflict_sql, quote_ident, quote_idents, Column, Dialect, Table,
+};
+use crate::RecordSink;
+
+// Loads records into PostgreSQL. The table is created from the record's
+// columns, and columns added to the record later are added to the table.
+// Every `batch_size` records are `COPY`-ed into a staging table and then
+// upserted into the target table by primary key.
+pub struct PostgresSink {
+    // Only taken when dropped
+    client: Option<Client>,
+    staging: String,
+    columns: Vec<Column>,
+    copy: String,
+    upsert: String,
+    rows: Vec<Map<String, Value>>,
+    batch_size: usize,
+}
+
+// The postgres client drives its own runtime, which must not be entered from
+// a runtime thread; hand the thread over while talking to the server. A
+// current-thread runtime cannot give its only thread away, so the client runs
+// on a thread of its own there.
+fn blocking<T: Send>(f: impl FnOnce() -> T + Send) -> T {
+    use tokio::runtime::{Handle, RuntimeFlavor};
+
+    match Handle::try_current() {
+        Ok(handle) if handle.runtime_flavor() == RuntimeFlavor::MultiThread => {
+            tokio::task::block_in_place(f)
+        }
+        Ok(_) => std::thread::scope(|scope| match scope.spawn(f).join() {
+            Ok(value) => value,
+            Err(panic) => std::panic::resume_unwind(panic),
+        }),
+        Err(_) => f(),
+    }
+}
+
+impl PostgresSink {
+    // Works inside any tokio runtime as well as outside one, but blocks the
+    // calling thread while talking to the server.
+    pub fn connect<R: Table>(params: &str) -> anyhow::Result<Self> {
+        let client = blocking(|| Client::connect(params, NoTls))?;
+        Self::new::<R>(client)
+    }
+
+    pub fn new<R: Table>(mut client: Client) -> anyhow::Result<Self> {
+        let migration = migration_sql::<R>();
+        blocking(|| client.batch_execute(&migration.join(";\n")))?;
+
+        Ok(Self {
+            client: Some(client),
+            staging: staging_table::<R>(),
+            columns: R::columns(),
+            copy: copy_sql::<R>(),
+            upsert: upsert_from_staging_sql::<R>(),
+            rows: vec![],
+            batch_size: 1000,
+        })
+    }
+
+    pub fn batch_size(mut self, batch_size: usize) -> Self {
+        self.batch_size = batch_size;
+        self
+    }
+
+    pub fn client(&mut self) -> &mut Client {
+        self.client.as_mut().unwrap()
+    }
+
+    fn copy_batch(&mut self) -> anyhow::Result<()> {
+        if self.rows.is_empty() {
+            return Ok(());
+        }
+        let mut data = vec![];
+        for row in self.rows.iter() {
+            let fields = self
+                .columns
+                .iter()
+                .map(|c| copy_field(row.get(c.name)))
+                .collect::<Vec<String>>();
+            writeln!(data, "{}", fields.join(","))?;
+        }
+
+        let (copy, upsert, staging) = (&self.copy, &self.upsert, &self.staging);
+        let client = self.client.as_mut().unwrap();
+        blocking(|| -> anyhow::Result<()> {
+            let mut tx = client.transaction()?;
+            let mut writer = tx.copy_in(copy.as_str())?;
+            writer.write_all(&data)?;
+            writer.finish()?;
+            tx.batch_execute(&format!("{}; TRUNCATE {}", upsert, staging))?;
+            tx.commit()?;
+            Ok(())
+        })?;
+        self.rows.clear();
+        Ok(())
+    }
+}
+
+// Closing the connection waits on the client's runtime as well
+impl Drop for PostgresSink {
+    fn drop(&mut self) {
+        if let Some(client) = self.client.take() {
+            blocking(|| drop(client));
+        }
+    }
+}
+
+fn staging_table<R: Table>() -> String {
+    quote_ident(&format!("octx_staging_{}", R::table_name()))
+}
+
+fn column_names<R: Table>() -> String {
+    let names = R::columns().iter().map(|c| c.name).collect::<Vec<&str>>();
+    quote_idents(&names)
+}
+
+// Creates the table, adds the columns missing from an older table and
+// prepares the session's staging table
+fn migration_sql<R: Table>() -> Vec<String> {
+    let table = quote_ident(R::table_name());
+    let staging = staging_table::<R>();
+
+    let mut migration = vec![create_table_sql::<R>(Dialect::Postgres)];
+    for column in R::columns().iter() {
+        migration.push(format!(
+            "ALTER TABLE {} ADD COLUMN IF NOT EXISTS {} {}",
+            table,
+            quote_ident(column.name),
+            Dialect::Postgres.sql_type(column.ty),
+        ));
+    }
+    migration.push(format!(
+        "CREATE TEMPORARY TABLE IF NOT EXISTS {} AS SELECT * FROM {} WITH NO DATA",
+        staging, table
+    ));
+    migration.push(format!(
+        "ALTER TABLE {} ADD COLUMN IF NOT EXISTS octx_seq BIGSERIAL",
+        staging
+    ));
+    migration
+}
+
+fn copy_sql<R: Table>() -> String {
+    format!(
+        "COPY {} ({}) FROM STDIN WITH (FORMAT csv)",
+        staging_table::<R>(),
+        column_names::<R>()
+    )
+}
+
+// The last write wins when a batch has the same key more than once
+fn upsert_from_staging_sql<R: Table>() -> String {
+    format!(
+        "INSERT INTO {table} ({names}) \
+         SELECT DISTINCT ON ({key}) {names} FROM {staging} ORDER BY {key}, octx_seq DESC \
+         {on_conflict}",
+        table = quote_ident(R::table_name()),
+        names = column_names::<R>(),
+        key = quote_idents(R::primary_key()),
+        staging = staging_table::<R>(),
+        on_conflict = on_conflict_sql::<R>(),
+    )
+}
+
+// A CSV field for COPY: an unquoted empty field is NULL, anything else is
+// quoted so that empty strings stay empty strings.
+fn copy_field(value: Option<&Value>) -> String {
+    let text = match value {
+        None | Some(Value::Null) => return String::new(),
+        Some(Value::String(s)) => s.clone(),
+        Some(other) => other.to_string(),
+    };
+    format!("\"{}\"", text.replace('"', "\"\""))
+}
+
+impl<R: Serialize> RecordSink<R> for PostgresSink {
+    fn write(&mut self, record: R) -> anyhow::Result<()> {
+        match serde_json::to_value(&record)? {
+            Value::Object(row) => self.rows.push(row),
+            _ => anyhow::bail!("record must be a struct"),
+        }
+        if self.rows.len() >= self.batch_size {
+            self.copy_batch()?;
+        }
+        Ok(())
+    }
+
+    fn flush(&mut self) -> anyhow::Result<()> {
+        self.copy_batch()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::issues::IssueRec;
+    use octocrab::models::issues::Issue;
+
+    #[test]
+    fn test_copy_field() {
+        assert_eq!(copy_field(None), "");
+        assert_eq!(copy_field(Some(&Value::String("".to_string()))), "\"\"");
+        assert_eq!(
+            copy_field(Some(&Value::String("say \"hi\"".to_string()))),
+            "\"say \"\"hi\"\"\""
+        );
+    }
+
+    #[test]
+    fn test_statements_quote_identifiers() {
+        use crate::labels::LabelRec;
+
+        let migration = migration_sql::<LabelRec>();
+        assert!(migration.contains(
+            &"ALTER TABLE \"labels\" ADD COLUMN IF NOT EXISTS \"default\" BOOLEAN".to_string()
+        ));
+        assert!(migration.contains(
+            &"ALTER TABLE \"octx_staging_labels\" ADD COLUMN IF NOT EXISTS octx_seq BIGSERIAL"
+                .to_string()
+        ));
+
+        let copy = copy_sql::<LabelRec>();
+        assert!(copy.starts_with("COPY \"octx_staging_labels\" (\"id\", "));
+        assert!(copy.contains(", \"default\", "));
+        assert!(copy.ends_with(") FROM STDIN WITH (FORMAT csv)"));
+
+        let upsert = upsert_from_staging_sql::<LabelRec>();
+        assert!(upsert.starts_with("INSERT INTO \"labels\" (\"id\", "));
+        assert!(upsert.contains(" FROM \"octx_staging_labels\" ORDER BY "));
+        assert!(upsert.contains("\"default\" = excluded.\"default\""));
+    }
+
+    // The client's own runtime would panic if started on this one's thread
+    #[tokio::test]
+    async fn test_blocking_on_current_thread_runtime() {
+        let value = blocking(|| {
+            let runtime = tokio::runtime::Builder::new_current_thread()
+                .build()
+                .unwrap();
+            runtime.block_on(async { 42 })
+        });
+        assert_eq!(value, 42);
+    }
+
+    // Needs a running server, e.g.
+    // docker run --rm -e POSTGRES_PASSWORD=octx -p 5432:5432 postgres
+    // OCTX_TEST_POSTGRES="host=localhost user=postgres password=octx" \
+    //     cargo test --features postgres -- --ignored
+    #[test]
+    #[ignore]
+    fn test_upsert_by_primary_key() -> Result<(), Box<dyn std::error::Error>> {
+        let params = std::env::var("OCTX_TEST_POSTGRES")?;
+        let mut sink = PostgresSink::connect::<IssueRec>(&params)?;
+        sink.client().batch_execute("TRUNCATE issues")?;
+        for title in ["Found a bug", "Found a bug (edited)"].iter() {
+            let model: Issue = serde_json::from_str(include_str!("../testdata/issue.json"))?;
+            let mut record: IssueRec = model.into();
+            record.title = title.to_string();
+            sink.write(record)?;
+        }
+        RecordSink::<IssueRec>::close(&mut sink)?;
+
+        let row = sink.client().query_one(
+            "SELECT COUNT(*), MAX(title), MAX(labels->>0) FROM issues",
+            &[],
+        )?;
+        assert_eq!(row.get::<_, i64>(0), 1);
+        assert_eq!(row.get::<_, String>(1), "Found a bug (edited)");
+        assert_eq!(row.get::<_, String>(2), "bug");
+
+        Ok(())
+    }
+}
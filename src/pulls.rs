@@ -1,9 +1,14 @@
+use async_stream::try_stream;
 use chrono::{DateTime, Utc};
+use futures::{pin_mut, stream, Stream, StreamExt, TryFutureExt, TryStreamExt};
+use octocrab::models::{pulls, Author, AuthorAssociation, IssueState};
 use url::Url;
 use serde::*;
 
 //use crate::commits::{Commit, GitCommit, GitUser, Object, UserId};
-use crate::commits::Commit;
+use crate::commits::{Commit, GitUser};
+use crate::checkpoint::Cursor;
+use crate::error::skip_failed;
 use crate::*;
 
 #[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
@@ -17,43 +22,55 @@ pub struct PullRequest {
     pub updated_at: Option<DateTime<Utc>>,
 }
 
-#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
-#[non_exhaustive]
-pub struct PullRequestFile {
-    pub sha: Option<String>,
-    pub filename: Option<String>,
-    pub additions: i32,
-    pub deletions: i32,
-    pub changes: i32,
-    pub blob_url: Option<Url>,
-    pub raw_url: Option<Url>,
-    pub contents_url: Option<Url>,
-    pub patch: Option<String>,
-
-    pub pull_request_number: Option<u64>,
-    pub sdc_repository: Option<String>,
-}
-
-#[derive(Serialize, Debug)]
-pub struct PrCommitRec {
-    pub sha: Option<String>,
-    pub node_id: Option<String>,
-    pub url: Option<String>,
-    pub html_url: Option<String>,
-    pub comments_url: Option<String>,
-    pub author_id: Option<i64>,
-    pub committer_id: Option<i64>,
-    pub author: Option<String>,    // Vec.to_json
-    pub committer: Option<String>, // Vec.to_json
-    pub parents: String,           // Vec.to_json
-    pub message: Option<String>,
-    pub authorized_at: Option<DateTime<Utc>>,
-    pub committed_at: Option<DateTime<Utc>>,
-    pub comment_count: i32,
-
-    pub pull_request_number: Option<u64>,
-
-    pub sdc_repository: String,
+record! {
+    #[table(
+        name = "pull_request_files",
+        primary_key = ["sdc_repository", "pull_request_number", "filename"]
+    )]
+    #[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+    #[non_exhaustive]
+    pub struct PullRequestFile {
+        pub sha: Option<String>,
+        pub filename: Option<String>,
+        pub additions: i32,
+        pub deletions: i32,
+        pub changes: i32,
+        pub blob_url: Option<Url>,
+        pub raw_url: Option<Url>,
+        pub contents_url: Option<Url>,
+        pub patch: Option<String>,
+
+        pub pull_request_number: Option<u64>,
+        pub sdc_repository: Option<String>,
+    }
+}
+
+record! {
+    #[table(
+        name = "pull_request_commits",
+        primary_key = ["sdc_repository", "pull_request_number", "sha"]
+    )]
+    #[derive(Serialize, Debug)]
+    pub struct PrCommitRec {
+        pub sha: Option<String>,
+        pub node_id: Option<String>,
+        pub url: Option<String>,
+        pub html_url: Option<String>,
+        pub comments_url: Option<String>,
+        pub author_id: Option<i64>,
+        pub committer_id: Option<i64>,
+        pub author: Option<GitUser>,
+        pub committer: Option<GitUser>,
+        pub parents: String, // Vec.to_json
+        pub message: Option<String>,
+        pub authorized_at: Option<DateTime<Utc>>,
+        pub committed_at: Option<DateTime<Utc>>,
+        pub comment_count: i32,
+
+        pub pull_request_number: Option<u64>,
+
+        pub sdc_repository: String,
+    }
 }
 
 impl RepositryAware for PrCommitRec {
@@ -70,20 +87,12 @@ impl From<Commit> for PrCommitRec {
             url: from.url,
             html_url: from.html_url,
             comments_url: from.comments_url,
-            author_id: from.author.map(|u| u.id).flatten(),
-            committer_id: from.committer.map(|u| u.id).flatten(),
-            author: from
-                .commit
-                .author
-                .as_ref()
-                .map(|d| serde_json::to_string(d).ok())
-                .flatten(),
-            committer: from
-                .commit
-                .committer
-                .as_ref()
-                .map(|d| serde_json::to_string(d).ok())
-                .flatten(),
+            author_id: from.author.and_then(|u| u.id),
+            committer_id: from.committer.and_then(|u| u.id),
+            authorized_at: from.commit.author.as_ref().map(|a| a.date),
+            committed_at: from.commit.committer.as_ref().map(|a| a.date),
+            author: from.commit.author,
+            committer: from.commit.committer,
             parents: from
                 .parents
                 .iter()
@@ -91,8 +100,6 @@ impl From<Commit> for PrCommitRec {
                 .collect::<Vec<String>>()
                 .join(" "),
             message: from.commit.message,
-            authorized_at: from.commit.author.as_ref().map(|a| a.date),
-            committed_at: from.commit.committer.as_ref().map(|a| a.date),
             comment_count: from.commit.comment_count,
 
             pull_request_number: None,
@@ -102,11 +109,133 @@ impl From<Commit> for PrCommitRec {
     }
 }
 
+record! {
+    #[table(name = "pull_requests", watermark = updated_at)]
+    #[derive(Serialize, Debug)]
+    pub struct PullRequestRec {
+        pub id: i64,
+        pub node_id: Option<String>,
+        pub url: String,
+        pub html_url: Option<Url>,
+        pub number: u64,
+        pub state: Option<IssueState>,
+        pub title: Option<String>,
+        pub body: Option<String>,
+        pub user_id: Option<i64>,
+        pub labels: Vec<String>,
+        pub milestone: Option<String>,
+        pub assignees: Vec<String>,
+        pub requested_reviewers: Vec<String>,
+        pub requested_teams: Vec<String>,
+        pub author_association: Option<AuthorAssociation>,
+        pub locked: bool,
+        pub draft: Option<bool>,
+        pub head_ref: String,
+        pub head_sha: String,
+        pub head_repository: Option<String>,
+        pub base_ref: String,
+        pub base_sha: String,
+        pub merged: Option<bool>,
+        pub merged_at: Option<DateTime<Utc>>,
+        pub merged_by_id: Option<i64>,
+        pub merge_commit_sha: Option<String>,
+        // Computed by GitHub in the background, so null until it has been
+        pub mergeable: Option<bool>,
+        pub mergeable_state: Option<String>,
+        pub rebaseable: Option<bool>,
+        pub additions: Option<u64>,
+        pub deletions: Option<u64>,
+        pub changed_files: Option<u64>,
+        pub commits: Option<u64>,
+        pub comments: Option<u64>,
+        pub review_comments: Option<u64>,
+        pub created_at: Option<DateTime<Utc>>,
+        pub updated_at: Option<DateTime<Utc>>,
+        pub closed_at: Option<DateTime<Utc>>,
+
+        pub sdc_repository: String,
+    }
+}
+
+impl RepositryAware for PullRequestRec {
+    fn set_repository(&mut self, name: String) {
+        self.sdc_repository = name;
+    }
+}
+
+impl From<pulls::PullRequest> for PullRequestRec {
+    fn from(from: pulls::PullRequest) -> Self {
+        let logins = |users: Option<Vec<Author>>| -> Vec<String> {
+            users
+                .unwrap_or_default()
+                .into_iter()
+                .map(|u| u.login)
+                .collect()
+        };
+
+        Self {
+            id: from.id.0 as i64,
+            node_id: from.node_id,
+            url: from.url,
+            html_url: from.html_url,
+            number: from.number,
+            state: from.state,
+            title: from.title,
+            body: from.body,
+            user_id: from.user.map(|u| u.id.0 as i64),
+            labels: from
+                .labels
+                .unwrap_or_default()
+                .into_iter()
+                .map(|l| l.name)
+                .collect(),
+            milestone: from.milestone.map(|m| m.title),
+            assignees: logins(from.assignees),
+            requested_reviewers: logins(from.requested_reviewers),
+            requested_teams: from
+                .requested_teams
+                .unwrap_or_default()
+                .into_iter()
+                .map(|t| t.slug)
+                .collect(),
+            author_association: from.author_association,
+            locked: from.locked,
+            draft: from.draft,
+            head_ref: from.head.ref_field,
+            head_sha: from.head.sha,
+            head_repository: from.head.repo.and_then(|r| r.full_name),
+            base_ref: from.base.ref_field,
+            base_sha: from.base.sha,
+            merged: from.merged,
+            merged_at: from.merged_at,
+            merged_by_id: from.merged_by.map(|u| u.id.0 as i64),
+            merge_commit_sha: from.merge_commit_sha,
+            mergeable: from.mergeable,
+            mergeable_state: from.mergeable_state.as_ref().map(enum_to_string),
+            rebaseable: from.rebaseable,
+            additions: from.additions,
+            deletions: from.deletions,
+            changed_files: from.changed_files,
+            commits: from.commits,
+            comments: from.comments,
+            review_comments: from.review_comments,
+            created_at: from.created_at,
+            updated_at: from.updated_at,
+            closed_at: from.closed_at,
+
+            sdc_repository: String::default(),
+        }
+    }
+}
+
 pub struct PullFileFetcher {
     owner: String,
     name: String,
     since: Option<DateTime<Utc>>,
     octocrab: octocrab::Octocrab,
+    cursor: Cursor,
+    concurrency: usize,
+    skip_errors: bool,
 }
 
 impl PullFileFetcher {
@@ -121,105 +250,362 @@ impl PullFileFetcher {
             name,
             since,
             octocrab,
+            cursor: Cursor::default(),
+            concurrency: PULL_CONCURRENCY,
+            skip_errors: false,
         }
     }
+
+    pub fn with_cursor(mut self, cursor: Cursor) -> Self {
+        self.cursor = cursor;
+        self
+    }
+
+    // Pull requests of a page whose files or commits are fetched at a time
+    pub fn with_concurrency(mut self, concurrency: usize) -> Self {
+        self.concurrency = concurrency.max(1);
+        self
+    }
+
+    // Log and leave out a pull request whose files or commits cannot be
+    // fetched instead of failing
+    pub fn with_skip_errors(mut self, yes: bool) -> Self {
+        self.skip_errors = yes;
+        self
+    }
 }
 
 impl PullFileFetcher {
-    fn pulls_route(&self) -> String {
+    fn pulls_route(&self) -> crate::Result<String> {
         let param = Params::default();
-        format!(
+        Ok(format!(
             "/repos/{owner}/{repo}/pulls?{query}&state=all&sort=updated&direction=desc",
             owner = &self.owner,
             repo = &self.name,
-            query = param.to_query(),
-        )
-    }
-
-    pub async fn fetch<T: std::io::Write>(&self, mut wtr: csv::Writer<T>) -> octocrab::Result<()> {
-        let first: octocrab::Page<PullRequest> =
-            self.octocrab.get(&self.pulls_route(), None::<&()>).await?;
-        let mut page_opt = Some(first);
-
-        while let Some(mut page) = page_opt {
-            let pulls: Vec<PullRequest> = page.take_items();
-            let mut last_update: Option<DateTime<Utc>> = None;
-            for pull in pulls.into_iter() {
-                let files_route = format!(
-                    "/repos/{owner}/{repo}/pulls/{number}/files",
-                    owner = &self.owner,
-                    repo = &self.name,
-                    number = pull.number,
-                );
-                let mut files: Vec<PullRequestFile> =
-                    self.octocrab.get(&files_route, None::<&()>).await?;
-                for file in files.iter_mut() {
-                    file.pull_request_number = pull.number.into();
-                    file.sdc_repository = format!("{}/{}", self.owner, self.name).into();
-
-                    wtr.serialize(file).expect("Serialize failed");
-                }
+            query = param.to_query()?,
+        ))
+    }
 
-                last_update = Some(pull.updated_at.unwrap_or_else(|| pull.created_at));
-            }
+    // e.g. octocat/Hello-World#1347
+    fn pull_name(&self, pull: &PullRequest) -> String {
+        format!("{}/{}#{}", self.owner, self.name, pull.number)
+    }
+
+    async fn pull_files(&self, number: u64) -> octocrab::Result<Vec<PullRequestFile>> {
+        let files_route = format!(
+            "/repos/{owner}/{repo}/pulls/{number}/files",
+            owner = &self.owner,
+            repo = &self.name,
+            number = number,
+        );
+        self.octocrab.get(&files_route, None::<&()>).await
+    }
 
-            let next = if let Some(since) = self.since {
-                if last_update.unwrap() < since {
-                    None
-                } else {
-                    page.next.map(to_relative_uri)
+    async fn pull_commits(&self, number: u64) -> octocrab::Result<Vec<Commit>> {
+        let commits_route = format!(
+            "/repos/{owner}/{repo}/pulls/{number}/commits",
+            owner = &self.owner,
+            repo = &self.name,
+            number = number,
+        );
+        self.octocrab.get(&commits_route, None::<&()>).await
+    }
+
+    // Files of the pull requests on each page are fetched `concurrency` pull
+    // requests at a time, and yielded in the order of the page.
+    pub fn stream(&self) -> impl Stream<Item = crate::Result<PullRequestFile>> + '_ {
+        try_stream! {
+            let pages = self.cursor.pages::<PullRequest>(&self.octocrab, self.pulls_route()?);
+            pin_mut!(pages);
+            while let Some(mut page) = pages.try_next().await? {
+                let pulls: Vec<PullRequest> = page.take_items();
+                let last_update = pulls
+                    .last()
+                    .map(|pull| pull.updated_at.unwrap_or(pull.created_at));
+                let files = stream::iter(pulls.iter())
+                    .map(|pull| {
+                        let item = format!("files of pull request {}", self.pull_name(pull));
+                        self.pull_files(pull.number).map_err(|e| Error::item(item, e))
+                    })
+                    .buffered(self.concurrency);
+                pin_mut!(files);
+                let mut pulls = pulls.iter();
+                while let Some(files) = files.next().await {
+                    let number = pulls.next().unwrap().number;
+                    for mut file in skip_failed(files, self.skip_errors)? {
+                        file.pull_request_number = number.into();
+                        file.sdc_repository = format!("{}/{}", self.owner, self.name).into();
+
+                        yield file;
+                    }
                 }
-            } else {
-                page.next.map(to_relative_uri)
-            };
-            page_opt = self.octocrab.get_page(&next).await?;
-        }
 
-        Ok(())
+                if let Some(since) = self.since {
+                    if last_update.is_none_or(|last| last < since) {
+                        break;
+                    }
+                }
+            }
+        }
     }
 
-    pub async fn fetch_commits<T: std::io::Write>(
+    pub async fn fetch<S: RecordSink<PullRequestFile> + ?Sized>(
         &self,
-        mut wtr: csv::Writer<T>,
-    ) -> octocrab::Result<()> {
-        let first: octocrab::Page<PullRequest> =
-            self.octocrab.get(&self.pulls_route(), None::<&()>).await?;
-        let mut page_opt = Some(first);
-
-        while let Some(mut page) = page_opt {
-            let pulls: Vec<PullRequest> = page.take_items();
-            let mut last_update: Option<DateTime<Utc>> = None;
-            for pull in pulls.into_iter() {
-                let commits_route = format!(
-                    "/repos/{owner}/{repo}/pulls/{number}/commits",
-                    owner = &self.owner,
-                    repo = &self.name,
-                    number = pull.number,
-                );
-                let commits: Vec<Commit> =
-                    self.octocrab.get(&commits_route, None::<&()>).await?;
-                for commit in commits.into_iter() {
-                    let mut commit: PrCommitRec = commit.into();
-                    commit.pull_request_number = pull.number.into();
-                    commit.set_repository(format!("{}/{}", self.owner, self.name));
-
-                    wtr.serialize(commit).expect("Serialize failed");
+        sink: &mut S,
+    ) -> crate::Result<()> {
+        write_stream(self.stream(), sink).await
+    }
+
+    pub fn stream_commits(&self) -> impl Stream<Item = crate::Result<PrCommitRec>> + '_ {
+        try_stream! {
+            let pages = self.cursor.pages::<PullRequest>(&self.octocrab, self.pulls_route()?);
+            pin_mut!(pages);
+            while let Some(mut page) = pages.try_next().await? {
+                let pulls: Vec<PullRequest> = page.take_items();
+                let last_update = pulls
+                    .last()
+                    .map(|pull| pull.updated_at.unwrap_or(pull.created_at));
+                let commits = stream::iter(pulls.iter())
+                    .map(|pull| {
+                        let item = format!("commits of pull request {}", self.pull_name(pull));
+                        self.pull_commits(pull.number).map_err(|e| Error::item(item, e))
+                    })
+                    .buffered(self.concurrency);
+                pin_mut!(commits);
+                let mut pulls = pulls.iter();
+                while let Some(commits) = commits.next().await {
+                    let number = pulls.next().unwrap().number;
+                    for commit in skip_failed(commits, self.skip_errors)? {
+                        let mut commit: PrCommitRec = commit.into();
+                        commit.pull_request_number = number.into();
+                        commit.set_repository(format!("{}/{}", self.owner, self.name));
+
+                        yield commit;
+                    }
                 }
 
-                last_update = Some(pull.updated_at.unwrap_or_else(|| pull.created_at));
+                if let Some(since) = self.since {
+                    if last_update.is_none_or(|last| last < since) {
+                        break;
+                    }
+                }
             }
+        }
+    }
 
-            let next = if let Some(since) = self.since {
-                if last_update.unwrap() < since {
-                    None
-                } else {
-                    page.next.map(to_relative_uri)
+    pub async fn fetch_commits<S: RecordSink<PrCommitRec> + ?Sized>(
+        &self,
+        sink: &mut S,
+    ) -> crate::Result<()> {
+        write_stream(self.stream_commits(), sink).await
+    }
+}
+
+// Pull requests themselves. The listing leaves out merge and diff details,
+// so each pull request is fetched on its own, `concurrency` at a time.
+pub struct PullFetcher {
+    owner: String,
+    name: String,
+    since: Option<DateTime<Utc>>,
+    octocrab: octocrab::Octocrab,
+    cursor: Cursor,
+    concurrency: usize,
+    skip_errors: bool,
+}
+
+impl PullFetcher {
+    pub fn new(
+        owner: String,
+        name: String,
+        since: Option<DateTime<Utc>>,
+        octocrab: octocrab::Octocrab,
+    ) -> Self {
+        Self {
+            owner,
+            name,
+            since,
+            octocrab,
+            cursor: Cursor::default(),
+            concurrency: PULL_CONCURRENCY,
+            skip_errors: false,
+        }
+    }
+
+    pub fn with_cursor(mut self, cursor: Cursor) -> Self {
+        self.cursor = cursor;
+        self
+    }
+
+    pub fn with_concurrency(mut self, concurrency: usize) -> Self {
+        self.concurrency = concurrency.max(1);
+        self
+    }
+
+    // Log and leave out a pull request that cannot be fetched instead of
+    // failing
+    pub fn with_skip_errors(mut self, yes: bool) -> Self {
+        self.skip_errors = yes;
+        self
+    }
+}
+
+impl UrlConstructor for PullFetcher {
+    fn reponame(&self) -> String {
+        format!("{}/{}", self.owner, self.name)
+    }
+
+    fn entrypoint_route(&self) -> crate::Result<String> {
+        let param = Params::default();
+        Ok(format!(
+            "/repos/{owner}/{repo}/pulls?{query}&state=all&sort=updated&direction=desc",
+            owner = &self.owner,
+            repo = &self.name,
+            query = param.to_query()?,
+        ))
+    }
+}
+
+impl PullFetcher {
+    async fn pull(&self, number: u64) -> octocrab::Result<PullRequestRec> {
+        let route = format!(
+            "/repos/{owner}/{repo}/pulls/{number}",
+            owner = &self.owner,
+            repo = &self.name,
+            number = number,
+        );
+        let pull: pulls::PullRequest = self.octocrab.get(&route, None::<&()>).await?;
+        let mut pull: PullRequestRec = pull.into();
+        pull.set_repository(self.reponame());
+        Ok(pull)
+    }
+
+    pub fn stream(&self) -> impl Stream<Item = crate::Result<PullRequestRec>> + '_ {
+        try_stream! {
+            let pages = self.cursor.pages::<PullRequest>(&self.octocrab, self.entrypoint_route()?);
+            pin_mut!(pages);
+            while let Some(mut page) = pages.try_next().await? {
+                let pulls: Vec<PullRequest> = page.take_items();
+                let last_update = pulls
+                    .last()
+                    .map(|pull| pull.updated_at.unwrap_or(pull.created_at));
+                let details = stream::iter(pulls.iter())
+                    .map(|pull| {
+                        let item = format!("pull request {}#{}", self.reponame(), pull.number);
+                        self.pull(pull.number)
+                            .map_ok(Some)
+                            .map_err(|e| Error::item(item, e))
+                    })
+                    .buffered(self.concurrency);
+                pin_mut!(details);
+                while let Some(pull) = details.next().await {
+                    if let Some(pull) = skip_failed(pull, self.skip_errors)? {
+                        yield pull;
+                    }
                 }
-            } else {
-                page.next.map(to_relative_uri)
-            };
-            page_opt = self.octocrab.get_page(&next).await?;
+
+                if let Some(since) = self.since {
+                    if last_update.is_none_or(|last| last < since) {
+                        break;
+                    }
+                }
+            }
         }
+    }
+
+    pub async fn fetch<S: RecordSink<PullRequestRec> + ?Sized>(
+        &self,
+        sink: &mut S,
+    ) -> crate::Result<()> {
+        write_stream(self.stream(), sink).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_server::serve_pulls;
+
+    #[test]
+    fn test_convert_pull_request_model() -> Result<(), Box<dyn std::error::Error>> {
+        let model: pulls::PullRequest =
+            serde_json::from_str(include_str!("../testdata/pull.json"))?;
+
+        let record: PullRequestRec = model.into();
+
+        assert_eq!(record.number, 1347);
+        assert_eq!(record.head_ref, "new-topic".to_string());
+        assert_eq!(record.base_ref, "master".to_string());
+        assert_eq!(
+            record.head_repository,
+            Some("octocat/Hello-World".to_string())
+        );
+        assert_eq!(record.merged, Some(false));
+        assert_eq!(record.merged_by_id, Some(1));
+        assert_eq!(record.draft, Some(false));
+        assert_eq!(record.requested_reviewers, vec!["octocat".to_string()]);
+        assert_eq!(record.requested_teams, vec!["justice-league".to_string()]);
+        assert_eq!(record.mergeable_state, Some("clean".to_string()));
+        assert_eq!(record.additions, Some(100));
+        assert_eq!(record.changed_files, Some(5));
+
+        Ok(())
+    }
+
+    // Pull requests are yielded in the order of the listing, although the
+    // earlier ones take longer to fetch
+    #[tokio::test]
+    async fn test_pulls_in_listing_order() -> Result<(), Box<dyn std::error::Error>> {
+        let numbers = [1, 2, 3, 4];
+        let octocrab = serve_pulls(&numbers, "", |n| {
+            let mut pull: serde_json::Value =
+                serde_json::from_str(include_str!("../testdata/pull.json")).unwrap();
+            pull["number"] = n.into();
+            pull
+        })
+        .await;
+
+        let fetcher = PullFetcher::new(
+            "octocat".to_string(),
+            "Hello-World".to_string(),
+            None,
+            octocrab,
+        );
+        let pulls: Vec<PullRequestRec> = fetcher.stream().try_collect().await?;
+        let fetched = pulls.iter().map(|pull| pull.number).collect::<Vec<u64>>();
+        assert_eq!(fetched, numbers.to_vec());
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_pull_files_in_listing_order() -> Result<(), Box<dyn std::error::Error>> {
+        let numbers = [1, 2, 3, 4];
+        let octocrab = serve_pulls(&numbers, "/files", |n| {
+            serde_json::json!([{
+                "filename": format!("file{}.txt", n),
+                "additions": 1,
+                "deletions": 0,
+                "changes": 1,
+            }])
+        })
+        .await;
+
+        let fetcher = PullFileFetcher::new(
+            "octocat".to_string(),
+            "Hello-World".to_string(),
+            None,
+            octocrab,
+        );
+        let files: Vec<PullRequestFile> = fetcher.stream().try_collect().await?;
+        let fetched = files
+            .iter()
+            .map(|file| (file.pull_request_number, file.filename.clone()))
+            .collect::<Vec<(Option<u64>, Option<String>)>>();
+        let expected = numbers
+            .iter()
+            .map(|n| (Some(*n), Some(format!("file{}.txt", n))))
+            .collect::<Vec<(Option<u64>, Option<String>)>>();
+        assert_eq!(fetched, expected);
 
         Ok(())
     }
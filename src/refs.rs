@@ -0,0 +1,450 @@
+use async_stream::try_stream;
+use futures::{pin_mut, stream, Stream, StreamExt, TryFutureExt, TryStreamExt};
+use octocrab::models::repos::{Branch, Tag};
+use serde::*;
+use url::Url;
+
+use crate::checkpoint::Cursor;
+use crate::error::skip_failed;
+use crate::*;
+
+record! {
+    #[table(name = "branches", primary_key = ["sdc_repository", "name"])]
+    #[derive(Serialize, Debug)]
+    pub struct BranchRec {
+        pub name: String,
+        pub sha: String,
+        pub protected: bool,
+
+        pub sdc_repository: String,
+    }
+}
+
+impl RepositryAware for BranchRec {
+    fn set_repository(&mut self, name: String) {
+        self.sdc_repository = name;
+    }
+}
+
+impl From<Branch> for BranchRec {
+    fn from(from: Branch) -> Self {
+        Self {
+            name: from.name,
+            sha: from.commit.sha,
+            protected: from.protected,
+
+            sdc_repository: String::default(),
+        }
+    }
+}
+
+record! {
+    #[table(name = "tags", primary_key = ["sdc_repository", "name"])]
+    #[derive(Serialize, Debug)]
+    pub struct TagRec {
+        pub name: String,
+        pub node_id: String,
+        pub sha: String,
+        pub zipball_url: Url,
+        pub tarball_url: Url,
+
+        pub sdc_repository: String,
+    }
+}
+
+impl RepositryAware for TagRec {
+    fn set_repository(&mut self, name: String) {
+        self.sdc_repository = name;
+    }
+}
+
+impl From<Tag> for TagRec {
+    fn from(from: Tag) -> Self {
+        Self {
+            name: from.name,
+            node_id: from.node_id,
+            sha: from.commit.sha,
+            zipball_url: from.zipball_url,
+            tarball_url: from.tarball_url,
+
+            sdc_repository: String::default(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Enabled {
+    pub enabled: bool,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct RequiredStatusChecks {
+    pub strict: Option<bool>,
+    #[serde(default)]
+    pub contexts: Vec<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct RequiredPullRequestReviews {
+    pub dismiss_stale_reviews: Option<bool>,
+    pub require_code_owner_reviews: Option<bool>,
+    pub required_approving_review_count: Option<i64>,
+    pub require_last_push_approval: Option<bool>,
+}
+
+// Rules left unset are missing from the response, and which rules there are
+// depends on the GitHub (Enterprise Server) version, so every one is optional
+// ref: https://docs.github.com/en/rest/branches/branch-protection#get-branch-protection
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[non_exhaustive]
+pub struct BranchProtection {
+    pub url: Option<String>,
+    pub required_status_checks: Option<RequiredStatusChecks>,
+    pub enforce_admins: Option<Enabled>,
+    pub required_pull_request_reviews: Option<RequiredPullRequestReviews>,
+    pub required_linear_history: Option<Enabled>,
+    pub allow_force_pushes: Option<Enabled>,
+    pub allow_deletions: Option<Enabled>,
+    pub required_conversation_resolution: Option<Enabled>,
+    pub required_signatures: Option<Enabled>,
+}
+
+record! {
+    #[table(name = "branch_protections", primary_key = ["sdc_repository", "branch"])]
+    #[derive(Serialize, Debug)]
+    pub struct BranchProtectionRec {
+        pub branch: String,
+        pub url: Option<String>,
+        pub required_status_checks: bool,
+        pub strict_status_checks: Option<bool>,
+        pub status_check_contexts: Vec<String>,
+        pub enforce_admins: Option<bool>,
+        pub required_pull_request_reviews: bool,
+        pub required_approving_review_count: Option<i64>,
+        pub dismiss_stale_reviews: Option<bool>,
+        pub require_code_owner_reviews: Option<bool>,
+        pub require_last_push_approval: Option<bool>,
+        pub required_linear_history: Option<bool>,
+        pub allow_force_pushes: Option<bool>,
+        pub allow_deletions: Option<bool>,
+        pub required_conversation_resolution: Option<bool>,
+        pub required_signatures: Option<bool>,
+
+        pub sdc_repository: String,
+    }
+}
+
+impl RepositryAware for BranchProtectionRec {
+    fn set_repository(&mut self, name: String) {
+        self.sdc_repository = name;
+    }
+}
+
+impl From<BranchProtection> for BranchProtectionRec {
+    fn from(from: BranchProtection) -> Self {
+        let enabled = |rule: Option<Enabled>| rule.map(|r| r.enabled);
+        let checks = from.required_status_checks;
+        let reviews = from.required_pull_request_reviews;
+
+        Self {
+            branch: String::default(),
+            url: from.url,
+            required_status_checks: checks.is_some(),
+            strict_status_checks: checks.as_ref().and_then(|c| c.strict),
+            status_check_contexts: checks.map(|c| c.contexts).unwrap_or_default(),
+            enforce_admins: enabled(from.enforce_admins),
+            required_pull_request_reviews: reviews.is_some(),
+            required_approving_review_count: reviews
+                .as_ref()
+                .and_then(|r| r.required_approving_review_count),
+            dismiss_stale_reviews: reviews.as_ref().and_then(|r| r.dismiss_stale_reviews),
+            require_code_owner_reviews: reviews.as_ref().and_then(|r| r.require_code_owner_reviews),
+            require_last_push_approval: reviews.and_then(|r| r.require_last_push_approval),
+            required_linear_history: enabled(from.required_linear_history),
+            allow_force_pushes: enabled(from.allow_force_pushes),
+            allow_deletions: enabled(from.allow_deletions),
+            required_conversation_resolution: enabled(from.required_conversation_resolution),
+            required_signatures: enabled(from.required_signatures),
+
+            sdc_repository: String::default(),
+        }
+    }
+}
+
+pub struct BranchFetcher {
+    owner: String,
+    name: String,
+    octocrab: octocrab::Octocrab,
+    cursor: Cursor,
+}
+
+impl BranchFetcher {
+    pub fn new(owner: String, name: String, octocrab: octocrab::Octocrab) -> Self {
+        Self {
+            owner,
+            name,
+            octocrab,
+            cursor: Cursor::default(),
+        }
+    }
+
+    pub fn with_cursor(mut self, cursor: Cursor) -> Self {
+        self.cursor = cursor;
+        self
+    }
+}
+
+impl UrlConstructor for BranchFetcher {
+    fn reponame(&self) -> String {
+        format!("{}/{}", self.owner, self.name)
+    }
+
+    fn entrypoint_route(&self) -> crate::Result<String> {
+        let param = Params::default();
+
+        Ok(format!(
+            "/repos/{owner}/{repo}/branches?{query}",
+            owner = &self.owner,
+            repo = &self.name,
+            query = param.to_query()?,
+        ))
+    }
+}
+
+impl LoopWriter for BranchFetcher {
+    type Model = Branch;
+    type Record = BranchRec;
+}
+
+impl BranchFetcher {
+    pub fn stream(&self) -> impl Stream<Item = crate::Result<BranchRec>> + '_ {
+        try_stream! {
+            let pages = self.cursor.pages::<Branch>(&self.octocrab, self.entrypoint_route()?);
+            pin_mut!(pages);
+            while let Some(mut page) = pages.try_next().await? {
+                for record in self.take_records(&mut page) {
+                    yield record;
+                }
+            }
+        }
+    }
+
+    pub async fn fetch<S: RecordSink<BranchRec> + ?Sized>(
+        &self,
+        sink: &mut S,
+    ) -> crate::Result<()> {
+        write_stream(self.stream(), sink).await
+    }
+}
+
+pub struct TagFetcher {
+    owner: String,
+    name: String,
+    octocrab: octocrab::Octocrab,
+    cursor: Cursor,
+}
+
+impl TagFetcher {
+    pub fn new(owner: String, name: String, octocrab: octocrab::Octocrab) -> Self {
+        Self {
+            owner,
+            name,
+            octocrab,
+            cursor: Cursor::default(),
+        }
+    }
+
+    pub fn with_cursor(mut self, cursor: Cursor) -> Self {
+        self.cursor = cursor;
+        self
+    }
+}
+
+impl UrlConstructor for TagFetcher {
+    fn reponame(&self) -> String {
+        format!("{}/{}", self.owner, self.name)
+    }
+
+    fn entrypoint_route(&self) -> crate::Result<String> {
+        let param = Params::default();
+
+        Ok(format!(
+            "/repos/{owner}/{repo}/tags?{query}",
+            owner = &self.owner,
+            repo = &self.name,
+            query = param.to_query()?,
+        ))
+    }
+}
+
+impl LoopWriter for TagFetcher {
+    type Model = Tag;
+    type Record = TagRec;
+}
+
+impl TagFetcher {
+    pub fn stream(&self) -> impl Stream<Item = crate::Result<TagRec>> + '_ {
+        try_stream! {
+            let pages = self.cursor.pages::<Tag>(&self.octocrab, self.entrypoint_route()?);
+            pin_mut!(pages);
+            while let Some(mut page) = pages.try_next().await? {
+                for record in self.take_records(&mut page) {
+                    yield record;
+                }
+            }
+        }
+    }
+
+    pub async fn fetch<S: RecordSink<TagRec> + ?Sized>(&self, sink: &mut S) -> crate::Result<()> {
+        write_stream(self.stream(), sink).await
+    }
+}
+
+// Fetches the protection of every protected branch, `concurrency` branches at
+// a time. Reading protection needs admin access to the repository.
+pub struct BranchProtectionFetcher {
+    owner: String,
+    name: String,
+    octocrab: octocrab::Octocrab,
+    cursor: Cursor,
+    concurrency: usize,
+    skip_errors: bool,
+}
+
+impl BranchProtectionFetcher {
+    pub fn new(owner: String, name: String, octocrab: octocrab::Octocrab) -> Self {
+        Self {
+            owner,
+            name,
+            octocrab,
+            cursor: Cursor::default(),
+            concurrency: PULL_CONCURRENCY,
+            skip_errors: false,
+        }
+    }
+
+    pub fn with_cursor(mut self, cursor: Cursor) -> Self {
+        self.cursor = cursor;
+        self
+    }
+
+    // Branches of a page whose protection is fetched at a time
+    pub fn with_concurrency(mut self, concurrency: usize) -> Self {
+        self.concurrency = concurrency.max(1);
+        self
+    }
+
+    // Log and leave out a branch whose protection cannot be fetched instead
+    // of failing
+    pub fn with_skip_errors(mut self, yes: bool) -> Self {
+        self.skip_errors = yes;
+        self
+    }
+}
+
+impl UrlConstructor for BranchProtectionFetcher {
+    fn reponame(&self) -> String {
+        format!("{}/{}", self.owner, self.name)
+    }
+
+    fn entrypoint_route(&self) -> crate::Result<String> {
+        let param = Params::default();
+
+        Ok(format!(
+            "/repos/{owner}/{repo}/branches?protected=true&{query}",
+            owner = &self.owner,
+            repo = &self.name,
+            query = param.to_query()?,
+        ))
+    }
+}
+
+impl BranchProtectionFetcher {
+    async fn protection(&self, branch: &str) -> octocrab::Result<BranchProtectionRec> {
+        // Git refs cannot have spaces, so nothing is encoded as '+'
+        let encoded: String = url::form_urlencoded::byte_serialize(branch.as_bytes()).collect();
+        let route = format!(
+            "/repos/{owner}/{repo}/branches/{branch}/protection",
+            owner = &self.owner,
+            repo = &self.name,
+            branch = encoded,
+        );
+        let protection: BranchProtection = self.octocrab.get(route, None::<&()>).await?;
+        let mut record: BranchProtectionRec = protection.into();
+        record.branch = branch.to_string();
+        record.set_repository(self.reponame());
+        Ok(record)
+    }
+
+    pub fn stream(&self) -> impl Stream<Item = crate::Result<BranchProtectionRec>> + '_ {
+        try_stream! {
+            let route = self.entrypoint_route()?;
+            let pages = self.cursor.pages::<Branch>(&self.octocrab, route);
+            pin_mut!(pages);
+            while let Some(mut page) = pages.try_next().await? {
+                let branches: Vec<Branch> = page.take_items();
+                let protections = stream::iter(branches.iter())
+                    .map(|branch| {
+                        let item = format!(
+                            "protection of branch {} of {}",
+                            branch.name,
+                            self.reponame()
+                        );
+                        self.protection(&branch.name)
+                            .map_ok(Some)
+                            .map_err(|e| Error::item(item, e))
+                    })
+                    .buffered(self.concurrency);
+                pin_mut!(protections);
+                while let Some(protection) = protections.next().await {
+                    if let Some(protection) = skip_failed(protection, self.skip_errors)? {
+                        yield protection;
+                    }
+                }
+            }
+        }
+    }
+
+    pub async fn fetch<S: RecordSink<BranchProtectionRec> + ?Sized>(
+        &self,
+        sink: &mut S,
+    ) -> crate::Result<()> {
+        write_stream(self.stream(), sink).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_convert_branch_protection() -> Result<(), Box<dyn std::error::Error>> {
+        let model: BranchProtection = serde_json::from_str(
+            r#"{
+                "url": "https://api.github.com/repos/octocat/Hello-World/branches/main/protection",
+                "required_status_checks": {"strict": true, "contexts": ["ci/test"]},
+                "enforce_admins": {"enabled": true},
+                "required_pull_request_reviews": {
+                    "dismiss_stale_reviews": true,
+                    "require_code_owner_reviews": false,
+                    "required_approving_review_count": 2
+                },
+                "allow_force_pushes": {"enabled": false}
+            }"#,
+        )?;
+
+        let record: BranchProtectionRec = model.into();
+
+        assert!(record.required_status_checks);
+        assert_eq!(record.strict_status_checks, Some(true));
+        assert_eq!(record.status_check_contexts, vec!["ci/test".to_string()]);
+        assert_eq!(record.enforce_admins, Some(true));
+        assert!(record.required_pull_request_reviews);
+        assert_eq!(record.required_approving_review_count, Some(2));
+        assert_eq!(record.require_last_push_approval, None);
+        assert_eq!(record.allow_force_pushes, Some(false));
+        assert_eq!(record.required_linear_history, None);
+
+        Ok(())
+    }
+}
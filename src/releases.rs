@@ -3,6 +3,10 @@ use octocrab::models::repos::Asset;
 use url::Url;
 use serde::*;
 
+use async_stream::try_stream;
+use futures::{pin_mut, Stream, TryStreamExt};
+
+use crate::checkpoint::Cursor;
 use crate::*;
 
 // use octocrab::models::repos::Release;
@@ -29,28 +33,31 @@ pub struct Release {
     pub assets: Vec<Asset>,
 }
 
-#[derive(Serialize, Debug)]
-pub struct ReleaseRec {
-    pub url: Url,
-    pub html_url: Url,
-    pub assets_url: Url,
-    pub upload_url: Url,
-    pub tarball_url: Option<Url>,
-    pub zipball_url: Option<Url>,
-    pub id: i64,
-    pub node_id: String,
-    pub tag_name: String,
-    pub target_commitish: String,
-    pub name: Option<String>,
-    pub body: Option<String>,
-    pub draft: bool,
-    pub prerelease: bool,
-    pub created_at: DateTime<Utc>,
-    pub published_at: Option<DateTime<Utc>>,
-    pub author_id: Option<i64>,
-    pub assets: String,
-
-    pub sdc_repository: String,
+record! {
+    #[table(name = "releases")]
+    #[derive(Serialize, Debug)]
+    pub struct ReleaseRec {
+        pub url: Url,
+        pub html_url: Url,
+        pub assets_url: Url,
+        pub upload_url: Url,
+        pub tarball_url: Option<Url>,
+        pub zipball_url: Option<Url>,
+        pub id: i64,
+        pub node_id: String,
+        pub tag_name: String,
+        pub target_commitish: String,
+        pub name: Option<String>,
+        pub body: Option<String>,
+        pub draft: bool,
+        pub prerelease: bool,
+        pub created_at: DateTime<Utc>,
+        pub published_at: Option<DateTime<Utc>>,
+        pub author_id: Option<i64>,
+        pub assets: String,
+
+        pub sdc_repository: String,
+    }
 }
 
 impl From<Release> for ReleaseRec {
@@ -95,6 +102,7 @@ pub struct ReleaseFetcher {
     owner: String,
     name: String,
     octocrab: octocrab::Octocrab,
+    cursor: Cursor,
 }
 
 impl ReleaseFetcher {
@@ -103,8 +111,14 @@ impl ReleaseFetcher {
             owner,
             name,
             octocrab,
+            cursor: Cursor::default(),
         }
     }
+
+    pub fn with_cursor(mut self, cursor: Cursor) -> Self {
+        self.cursor = cursor;
+        self
+    }
 }
 
 impl UrlConstructor for ReleaseFetcher {
@@ -112,15 +126,15 @@ impl UrlConstructor for ReleaseFetcher {
         format!("{}/{}", self.owner, self.name)
     }
 
-    fn entrypoint_route(&self) -> String {
+    fn entrypoint_route(&self) -> crate::Result<String> {
         let param = Params::default();
 
-        format!(
+        Ok(format!(
             "/repos/{owner}/{repo}/releases?{query}",
             owner = &self.owner,
             repo = &self.name,
-            query = param.to_query(),
-        )
+            query = param.to_query()?,
+        ))
     }
 }
 
@@ -130,17 +144,22 @@ impl LoopWriter for ReleaseFetcher {
 }
 
 impl ReleaseFetcher {
-    pub async fn fetch<T: std::io::Write>(&self, mut wtr: csv::Writer<T>) -> octocrab::Result<()> {
-        let first: octocrab::Page<Release> = self
-            .octocrab
-            .get(self.entrypoint_route(), None::<&()>)
-            .await?;
-        let mut next = self.write_and_continue(first, &mut wtr);
-
-        while let Some(page) = self.octocrab.get_page(&next).await? {
-            next = self.write_and_continue(page, &mut wtr);
+    pub fn stream(&self) -> impl Stream<Item = crate::Result<ReleaseRec>> + '_ {
+        try_stream! {
+            let pages = self.cursor.pages::<Release>(&self.octocrab, self.entrypoint_route()?);
+            pin_mut!(pages);
+            while let Some(mut page) = pages.try_next().await? {
+                for record in self.take_records(&mut page) {
+                    yield record;
+                }
+            }
         }
+    }
 
-        Ok(())
+    pub async fn fetch<S: RecordSink<ReleaseRec> + ?Sized>(
+        &self,
+        sink: &mut S,
+    ) -> crate::Result<()> {
+        write_stream(self.stream(), sink).await
     }
 }
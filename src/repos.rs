@@ -0,0 +1,136 @@
+use async_stream::try_stream;
+use futures::{pin_mut, Stream, TryStreamExt};
+use serde::*;
+
+use crate::*;
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[non_exhaustive]
+pub struct Repository {
+    pub name: String,
+    pub full_name: String,
+    pub private: bool,
+    #[serde(default)]
+    pub fork: bool,
+    #[serde(default)]
+    pub archived: bool,
+    // public, private or internal; not returned by older GHES
+    pub visibility: Option<String>,
+}
+
+impl Repository {
+    pub fn owner(&self) -> &str {
+        self.full_name.split('/').next().unwrap_or(&self.full_name)
+    }
+
+    pub fn visibility(&self) -> &str {
+        match &self.visibility {
+            Some(visibility) => visibility,
+            None if self.private => "private",
+            None => "public",
+        }
+    }
+}
+
+pub const VISIBILITIES: &[&str] = &["public", "private", "internal"];
+
+#[derive(Debug, Clone, Default)]
+pub struct RepoFilter {
+    pub skip_archived: bool,
+    pub skip_forks: bool,
+    pub visibility: Option<String>,
+}
+
+impl RepoFilter {
+    pub fn accepts(&self, repo: &Repository) -> bool {
+        if (self.skip_archived && repo.archived) || (self.skip_forks && repo.fork) {
+            return false;
+        }
+        self.visibility
+            .as_ref()
+            .is_none_or(|visibility| visibility == repo.visibility())
+    }
+}
+
+// Lists the repositories of an organization, to run other fetchers on each.
+pub struct OrgRepoFetcher {
+    org: String,
+    filter: RepoFilter,
+    octocrab: octocrab::Octocrab,
+}
+
+impl OrgRepoFetcher {
+    pub fn new(org: String, filter: RepoFilter, octocrab: octocrab::Octocrab) -> Self {
+        Self {
+            org,
+            filter,
+            octocrab,
+        }
+    }
+
+    fn entrypoint_route(&self) -> crate::Result<String> {
+        let param = Params::default();
+        Ok(format!(
+            "/orgs/{org}/repos?{query}&type=all",
+            org = &self.org,
+            query = param.to_query()?,
+        ))
+    }
+
+    pub fn stream(&self) -> impl Stream<Item = crate::Result<Repository>> + '_ {
+        try_stream! {
+            let pages = pages::<Repository>(&self.octocrab, self.entrypoint_route()?);
+            pin_mut!(pages);
+            while let Some(mut page) = pages.try_next().await? {
+                for repo in page.take_items() {
+                    if self.filter.accepts(&repo) {
+                        yield repo;
+                    }
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_filter_repositories() -> Result<(), Box<dyn std::error::Error>> {
+        let repos: Vec<Repository> = serde_json::from_str(
+            r#"[
+                {"name": "app", "full_name": "acme/app", "private": true, "visibility": "internal"},
+                {"name": "old", "full_name": "acme/old", "private": false, "archived": true},
+                {"name": "fork", "full_name": "acme/fork", "private": false, "fork": true}
+            ]"#,
+        )?;
+        let names = |filter: RepoFilter| {
+            repos
+                .iter()
+                .filter(|repo| filter.accepts(repo))
+                .map(|repo| repo.name.as_str())
+                .collect::<Vec<&str>>()
+        };
+
+        assert_eq!(names(RepoFilter::default()), vec!["app", "old", "fork"]);
+        assert_eq!(
+            names(RepoFilter {
+                skip_archived: true,
+                skip_forks: true,
+                ..Default::default()
+            }),
+            vec!["app"]
+        );
+        assert_eq!(
+            names(RepoFilter {
+                visibility: Some("public".to_string()),
+                ..Default::default()
+            }),
+            vec!["old", "fork"]
+        );
+        assert_eq!(repos[0].owner(), "acme");
+
+        Ok(())
+    }
+}
@@ -0,0 +1,229 @@
+use async_stream::try_stream;
+use futures::{pin_mut, Stream, TryStreamExt};
+use octocrab::models::{Author, AuthorAssociation};
+use serde::*;
+use url::Url;
+
+use crate::checkpoint::Cursor;
+use crate::*;
+
+type DateTime = chrono::DateTime<chrono::Utc>;
+
+// Copied from octocrab::models::pulls::ReviewComment, leaving out links and
+// reactions. Sides are kept as strings, as comments on a whole file have none
+// ref: https://docs.github.com/en/rest/pulls/comments#list-review-comments-in-a-repository
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[non_exhaustive]
+pub struct ReviewComment {
+    pub id: u64,
+    pub node_id: String,
+    pub url: Url,
+    pub html_url: Url,
+    pub pull_request_url: Url,
+    pub pull_request_review_id: Option<u64>,
+    #[serde(default)]
+    pub in_reply_to_id: Option<u64>,
+    pub diff_hunk: String,
+    pub path: String,
+    pub position: Option<u64>,
+    pub original_position: Option<u64>,
+    pub commit_id: String,
+    pub original_commit_id: String,
+    #[serde(default)]
+    pub start_line: Option<u64>,
+    #[serde(default)]
+    pub original_start_line: Option<u64>,
+    #[serde(default)]
+    pub start_side: Option<String>,
+    #[serde(default)]
+    pub line: Option<u64>,
+    #[serde(default)]
+    pub original_line: Option<u64>,
+    #[serde(default)]
+    pub side: Option<String>,
+    pub user: Option<Author>,
+    pub body: String,
+    pub author_association: Option<AuthorAssociation>,
+    pub created_at: DateTime,
+    pub updated_at: DateTime,
+}
+
+record! {
+    #[table(name = "review_comments", watermark = updated_at)]
+    #[derive(Serialize, Debug)]
+    pub struct ReviewCommentRec {
+        pub id: u64,
+        pub node_id: String,
+        pub url: Url,
+        pub html_url: Url,
+        pub pull_request_review_id: Option<u64>,
+        pub in_reply_to_id: Option<u64>,
+        pub diff_hunk: String,
+        pub path: String,
+        pub position: Option<u64>,
+        pub original_position: Option<u64>,
+        pub commit_id: String,
+        pub original_commit_id: String,
+        pub start_line: Option<u64>,
+        pub original_start_line: Option<u64>,
+        pub start_side: Option<String>,
+        pub line: Option<u64>,
+        pub original_line: Option<u64>,
+        pub side: Option<String>,
+        pub user_id: Option<i64>,
+        pub body: String,
+        pub author_association: Option<AuthorAssociation>,
+        pub created_at: DateTime,
+        pub updated_at: DateTime,
+
+        pub pull_request_number: Option<u64>,
+        pub sdc_repository: String,
+    }
+}
+
+impl RepositryAware for ReviewCommentRec {
+    fn set_repository(&mut self, name: String) {
+        self.sdc_repository = name;
+    }
+}
+
+impl From<ReviewComment> for ReviewCommentRec {
+    fn from(from: ReviewComment) -> Self {
+        // The listing covers every pull request, which is only told by the URL
+        let pull_request_number = from
+            .pull_request_url
+            .path_segments()
+            .and_then(|mut segments| segments.next_back())
+            .and_then(|number| number.parse().ok());
+
+        Self {
+            id: from.id,
+            node_id: from.node_id,
+            url: from.url,
+            html_url: from.html_url,
+            pull_request_review_id: from.pull_request_review_id,
+            in_reply_to_id: from.in_reply_to_id,
+            diff_hunk: from.diff_hunk,
+            path: from.path,
+            position: from.position,
+            original_position: from.original_position,
+            commit_id: from.commit_id,
+            original_commit_id: from.original_commit_id,
+            start_line: from.start_line,
+            original_start_line: from.original_start_line,
+            start_side: from.start_side,
+            line: from.line,
+            original_line: from.original_line,
+            side: from.side,
+            user_id: from.user.map(|u| u.id.0 as i64),
+            body: from.body,
+            author_association: from.author_association,
+            created_at: from.created_at,
+            updated_at: from.updated_at,
+
+            pull_request_number,
+            sdc_repository: String::default(),
+        }
+    }
+}
+
+pub struct ReviewCommentFetcher {
+    owner: String,
+    name: String,
+    since: Option<DateTime>,
+    octocrab: octocrab::Octocrab,
+    cursor: Cursor,
+}
+
+impl ReviewCommentFetcher {
+    pub fn new(
+        owner: String,
+        name: String,
+        since: Option<DateTime>,
+        octocrab: octocrab::Octocrab,
+    ) -> Self {
+        Self {
+            owner,
+            name,
+            since,
+            octocrab,
+            cursor: Cursor::default(),
+        }
+    }
+
+    pub fn with_cursor(mut self, cursor: Cursor) -> Self {
+        self.cursor = cursor;
+        self
+    }
+}
+
+impl UrlConstructor for ReviewCommentFetcher {
+    fn reponame(&self) -> String {
+        format!("{}/{}", self.owner, self.name)
+    }
+
+    fn entrypoint_route(&self) -> crate::Result<String> {
+        let param = Params {
+            since: self.since,
+            ..Default::default()
+        };
+
+        Ok(format!(
+            "/repos/{owner}/{repo}/pulls/comments?{query}",
+            owner = &self.owner,
+            repo = &self.name,
+            query = param.to_query()?,
+        ))
+    }
+}
+
+impl LoopWriter for ReviewCommentFetcher {
+    type Model = ReviewComment;
+    type Record = ReviewCommentRec;
+}
+
+impl ReviewCommentFetcher {
+    pub fn stream(&self) -> impl Stream<Item = crate::Result<ReviewCommentRec>> + '_ {
+        try_stream! {
+            let route = self.entrypoint_route()?;
+            let pages = self.cursor.pages::<ReviewComment>(&self.octocrab, route);
+            pin_mut!(pages);
+            while let Some(mut page) = pages.try_next().await? {
+                for record in self.take_records(&mut page) {
+                    yield record;
+                }
+            }
+        }
+    }
+
+    pub async fn fetch<S: RecordSink<ReviewCommentRec> + ?Sized>(
+        &self,
+        sink: &mut S,
+    ) -> crate::Result<()> {
+        write_stream(self.stream(), sink).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_convert_review_comment_model() -> Result<(), Box<dyn std::error::Error>> {
+        let model: ReviewComment =
+            serde_json::from_str(include_str!("../testdata/review_comment.json"))?;
+
+        let record: ReviewCommentRec = model.into();
+
+        assert_eq!(record.id, 10);
+        assert_eq!(record.pull_request_number, Some(1));
+        assert_eq!(record.pull_request_review_id, Some(42));
+        assert_eq!(record.in_reply_to_id, Some(8));
+        assert_eq!(record.path, "file1.txt".to_string());
+        assert_eq!(record.line, Some(2));
+        assert_eq!(record.side, Some("RIGHT".to_string()));
+        assert_eq!(record.user_id, Some(1));
+
+        Ok(())
+    }
+}
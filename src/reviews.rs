@@ -1,9 +1,13 @@
 extern crate octocrab;
+use async_stream::try_stream;
 use chrono::{DateTime, Utc};
+use futures::{pin_mut, stream, Stream, StreamExt, TryFutureExt, TryStreamExt};
 use octocrab::models::Author as User;
 use url::Url;
 use serde::{Deserialize, Serialize};
 
+use crate::checkpoint::Cursor;
+use crate::error::skip_failed;
 use crate::*;
 
 #[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
@@ -42,21 +46,24 @@ pub struct Review {
     pub author_association: Option<String>,
 }
 
-#[derive(Debug, Serialize)]
-pub struct ReviewRec {
-    pub id: u64,
-    pub node_id: String,
-    pub html_url: Url,
-    pub user_id: i64,
-    pub body: Option<String>,
-    pub commit_id: Option<String>,
-    pub state: Option<String>,
-    pub pull_request_url: Option<Url>,
-    pub submitted_at: Option<chrono::DateTime<chrono::Utc>>,
-    pub author_association: Option<String>,
+record! {
+    #[table(name = "reviews")]
+    #[derive(Debug, Serialize)]
+    pub struct ReviewRec {
+        pub id: u64,
+        pub node_id: String,
+        pub html_url: Url,
+        pub user_id: i64,
+        pub body: Option<String>,
+        pub commit_id: Option<String>,
+        pub state: Option<String>,
+        pub pull_request_url: Option<Url>,
+        pub submitted_at: Option<chrono::DateTime<chrono::Utc>>,
+        pub author_association: Option<String>,
 
-    pub pull_request_number: Option<u64>,
-    pub sdc_repository: String,
+        pub pull_request_number: Option<u64>,
+        pub sdc_repository: String,
+    }
 }
 
 impl RepositryAware for ReviewRec {
@@ -90,6 +97,9 @@ pub struct ReviewFetcher {
     name: String,
     since: Option<DateTime<Utc>>,
     octocrab: octocrab::Octocrab,
+    cursor: Cursor,
+    concurrency: usize,
+    skip_errors: bool,
 }
 
 impl ReviewFetcher {
@@ -104,74 +114,148 @@ impl ReviewFetcher {
             name,
             since,
             octocrab,
+            cursor: Cursor::default(),
+            concurrency: PULL_CONCURRENCY,
+            skip_errors: false,
         }
     }
+
+    pub fn with_cursor(mut self, cursor: Cursor) -> Self {
+        self.cursor = cursor;
+        self
+    }
+
+    // Pull requests of a page whose reviews are fetched at a time
+    pub fn with_concurrency(mut self, concurrency: usize) -> Self {
+        self.concurrency = concurrency.max(1);
+        self
+    }
+
+    // Log and leave out a pull request whose reviews cannot be fetched
+    // instead of failing
+    pub fn with_skip_errors(mut self, yes: bool) -> Self {
+        self.skip_errors = yes;
+        self
+    }
 }
 
 impl ReviewFetcher {
-    pub async fn fetch<T: std::io::Write>(&self, mut wtr: csv::Writer<T>) -> octocrab::Result<()> {
+    async fn pull_reviews(&self, number: u64) -> crate::Result<Vec<ReviewRec>> {
         let param = Params::default();
-        let pulls_route = format!(
-            "/repos/{owner}/{repo}/pulls?{query}&state=all&sort=updated&direction=desc",
+        let reviews_route = format!(
+            "/repos/{owner}/{repo}/pulls/{pull_number}/reviews?{query}",
             owner = &self.owner,
             repo = &self.name,
-            query = param.to_query(),
+            pull_number = number,
+            query = param.to_query()?,
         );
-        let first: octocrab::Page<PullRequest> =
-            self.octocrab.get(&pulls_route, None::<&()>).await?;
-        let mut page_opt = Some(first);
-
-        let mut pull_nums: Vec<u64> = vec![];
-        while let Some(mut page) = page_opt {
-            let pulls: Vec<PullRequest> = page.take_items();
-            let mut last_update: Option<DateTime<Utc>> = None;
-            for pull in pulls.into_iter() {
-                pull_nums.push(pull.number);
-                last_update = Some(pull.updated_at.unwrap_or_else(|| pull.created_at));
+        let pages = pages::<Review>(&self.octocrab, reviews_route);
+        pin_mut!(pages);
+        let mut reviews = vec![];
+        while let Some(mut page) = pages.try_next().await? {
+            for review in page.take_items() {
+                let mut review: ReviewRec = review.into();
+                review.sdc_repository = format!("{}/{}", self.owner, self.name);
+                review.pull_request_number = Some(number);
+                reviews.push(review);
             }
-
-            let next = if let Some(since) = self.since {
-                last_update.map_or_else(
-                    || None,
-                    |last| {
-                        if last < since {
-                            None
-                        } else {
-                            page.next.map(to_relative_uri)
-                        }
-                    },
-                )
-            } else {
-                page.next.map(to_relative_uri)
-            };
-            page_opt = self.octocrab.get_page(&next).await?;
         }
+        Ok(reviews)
+    }
 
-        for number in pull_nums.into_iter() {
-            let param = Params::default();
-            let reviews_route = format!(
-                "/repos/{owner}/{repo}/pulls/{pull_number}/reviews?{query}",
+    pub fn stream(&self) -> impl Stream<Item = crate::Result<ReviewRec>> + '_ {
+        try_stream! {
+            let query = Params::default().to_query()?;
+            let pulls_route = format!(
+                "/repos/{owner}/{repo}/pulls?{query}&state=all&sort=updated&direction=desc",
                 owner = &self.owner,
                 repo = &self.name,
-                pull_number = number,
-                query = param.to_query(),
+                query = query,
             );
-            let first: octocrab::Page<Review> =
-                self.octocrab.get(&reviews_route, None::<&()>).await?;
-            let mut page_opt = Some(first);
-            while let Some(mut page) = page_opt {
-                let reviews: Vec<Review> = page.take_items();
-                for review in reviews.into_iter() {
-                    let mut review: ReviewRec = review.into();
-                    review.sdc_repository = format!("{}/{}", self.owner, self.name);
-                    review.pull_request_number = Some(number);
-
-                    wtr.serialize(review).expect("Serialize failed");
+            let pulls = self.cursor.pages::<PullRequest>(&self.octocrab, pulls_route);
+            pin_mut!(pulls);
+
+            // Reviews are fetched page by page of pull requests, so that the
+            // cursor points at the pull requests the reviews belong to.
+            // Within a page, `concurrency` pull requests are fetched at a time
+            // and their reviews are yielded in the order of the page.
+            while let Some(mut page) = pulls.try_next().await? {
+                let pulls: Vec<PullRequest> = page.take_items();
+                let last_update = pulls
+                    .last()
+                    .map(|pull| pull.updated_at.unwrap_or(pull.created_at));
+                let reviews = stream::iter(pulls.iter())
+                    .map(|pull| {
+                        let item = format!(
+                            "reviews of pull request {}/{}#{}",
+                            self.owner, self.name, pull.number
+                        );
+                        self.pull_reviews(pull.number).map_err(|e| Error::item(item, e))
+                    })
+                    .buffered(self.concurrency);
+                pin_mut!(reviews);
+                while let Some(reviews) = reviews.next().await {
+                    for review in skip_failed(reviews, self.skip_errors)? {
+                        yield review;
+                    }
+                }
+
+                if let Some(since) = self.since {
+                    if last_update.is_none_or(|last| last < since) {
+                        break;
+                    }
                 }
-                let next = page.next.map(to_relative_uri);
-                page_opt = self.octocrab.get_page(&next).await?;
             }
         }
+    }
+
+    pub async fn fetch<S: RecordSink<ReviewRec> + ?Sized>(
+        &self,
+        sink: &mut S,
+    ) -> crate::Result<()> {
+        write_stream(self.stream(), sink).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_server::serve_pulls;
+
+    // Reviews are yielded in the order of the pull requests listed, although
+    // those of the earlier ones take longer to fetch
+    #[tokio::test]
+    async fn test_reviews_in_listing_order() -> Result<(), Box<dyn std::error::Error>> {
+        let pull: serde_json::Value = serde_json::from_str(include_str!("../testdata/pull.json"))?;
+        let numbers = [1, 2, 3, 4];
+        let octocrab = serve_pulls(&numbers, "/reviews", |n| {
+            serde_json::json!([{
+                "id": n * 10,
+                "node_id": "MDE3OlB1bGxSZXF1ZXN0UmV2aWV3ODA=",
+                "html_url": "https://github.com/octocat/Hello-World/pull/12#pullrequestreview-80",
+                "user": pull["user"],
+                "state": "APPROVED",
+            }])
+        })
+        .await;
+
+        let fetcher = ReviewFetcher::new(
+            "octocat".to_string(),
+            "Hello-World".to_string(),
+            None,
+            octocrab,
+        );
+        let reviews: Vec<ReviewRec> = fetcher.stream().try_collect().await?;
+        let fetched = reviews
+            .iter()
+            .map(|review| (review.pull_request_number, review.id))
+            .collect::<Vec<(Option<u64>, u64)>>();
+        let expected = numbers
+            .iter()
+            .map(|n| (Some(*n), n * 10))
+            .collect::<Vec<(Option<u64>, u64)>>();
+        assert_eq!(fetched, expected);
+
         Ok(())
     }
 }
@@ -0,0 +1,440 @@
+use chrono::{DateTime, Utc};
+use octocrab::models::{AuthorAssociation, IssueState};
+use std::str::FromStr;
+use url::Url;
+
+use crate::commits::GitUser;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ColumnType {
+    Boolean,
+    Int32,
+    Int64,
+    UInt32,
+    UInt64,
+    Text,
+    Timestamp,
+    // Nested values (arrays, objects); CSV puts them into a cell as a JSON string
+    Json,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Column {
+    pub name: &'static str,
+    pub ty: ColumnType,
+    pub nullable: bool,
+    // The field type as written in the record struct, e.g. `Option<i64>`
+    pub rust_type: &'static str,
+}
+
+impl Column {
+    pub fn of<T: ColumnKind>(name: &'static str, rust_type: &'static str) -> Self {
+        Self {
+            name: name.trim_start_matches("r#"),
+            ty: T::TYPE,
+            nullable: T::NULLABLE,
+            rust_type,
+        }
+    }
+}
+
+// Maps a record field type to its column type.
+pub trait ColumnKind {
+    const TYPE: ColumnType;
+    const NULLABLE: bool = false;
+}
+
+impl<T: ColumnKind> ColumnKind for Option<T> {
+    const TYPE: ColumnType = T::TYPE;
+    const NULLABLE: bool = true;
+}
+
+impl<T> ColumnKind for Vec<T> {
+    const TYPE: ColumnType = ColumnType::Json;
+}
+
+macro_rules! column_kind {
+    ($ty:ty, $column:ident) => {
+        impl ColumnKind for $ty {
+            const TYPE: ColumnType = ColumnType::$column;
+        }
+    };
+}
+
+column_kind!(bool, Boolean);
+column_kind!(i32, Int32);
+column_kind!(i64, Int64);
+column_kind!(u32, UInt32);
+column_kind!(u64, UInt64);
+column_kind!(String, Text);
+column_kind!(Url, Text);
+column_kind!(IssueState, Text);
+column_kind!(AuthorAssociation, Text);
+column_kind!(DateTime<Utc>, Timestamp);
+column_kind!(GitUser, Json);
+
+// A record type whose columns are known without fetching anything.
+pub trait Table {
+    fn table_name() -> &'static str;
+
+    fn columns() -> Vec<Column>;
+
+    fn primary_key() -> &'static [&'static str] {
+        &["id"]
+    }
+
+    // The timestamp the fetcher's `since` cutoff is compared with, which an
+    // incremental sync continues from.
+    fn watermark(&self) -> Option<DateTime<Utc>> {
+        None
+    }
+}
+
+// Field types that can be a record's watermark.
+pub trait Timestamp {
+    fn timestamp(&self) -> Option<DateTime<Utc>>;
+}
+
+impl Timestamp for DateTime<Utc> {
+    fn timestamp(&self) -> Option<DateTime<Utc>> {
+        Some(*self)
+    }
+}
+
+impl<T: Timestamp> Timestamp for Option<T> {
+    fn timestamp(&self) -> Option<DateTime<Utc>> {
+        self.as_ref().and_then(T::timestamp)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Dialect {
+    Sqlite,
+    Postgres,
+}
+
+impl Dialect {
+    pub fn sql_type(&self, ty: ColumnType) -> &'static str {
+        match self {
+            Dialect::Sqlite => match ty {
+                ColumnType::Boolean
+                | ColumnType::Int32
+                | ColumnType::Int64
+                | ColumnType::UInt32
+                | ColumnType::UInt64 => "INTEGER",
+                ColumnType::Text | ColumnType::Timestamp | ColumnType::Json => "TEXT",
+            },
+            Dialect::Postgres => match ty {
+                ColumnType::Boolean => "BOOLEAN",
+                ColumnType::Int32 => "INTEGER",
+                ColumnType::Int64 | ColumnType::UInt32 | ColumnType::UInt64 => "BIGINT",
+                ColumnType::Text => "TEXT",
+                ColumnType::Timestamp => "TIMESTAMPTZ",
+                ColumnType::Json => "JSONB",
+            },
+        }
+    }
+}
+
+// Identifiers are always quoted, as columns like `default` of labels are
+// reserved words.
+pub fn quote_ident(name: &str) -> String {
+    format!("\"{}\"", name.replace('"', "\"\""))
+}
+
+pub fn quote_idents(names: &[&str]) -> String {
+    names
+        .iter()
+        .map(|name| quote_ident(name))
+        .collect::<Vec<String>>()
+        .join(", ")
+}
+
+pub fn create_table_sql<R: Table>(dialect: Dialect) -> String {
+    let primary_key = R::primary_key();
+    let mut defs = R::columns()
+        .iter()
+        .map(|c| {
+            // SQLite would take NULL keys as distinct, and so never upsert
+            let nullable = c.nullable && !primary_key.contains(&c.name);
+            format!(
+                "    {} {}{}",
+                quote_ident(c.name),
+                dialect.sql_type(c.ty),
+                if nullable { "" } else { " NOT NULL" }
+            )
+        })
+        .collect::<Vec<String>>();
+    defs.push(format!("    PRIMARY KEY ({})", quote_idents(primary_key)));
+    format!(
+        "CREATE TABLE IF NOT EXISTS {} (\n{}\n)",
+        quote_ident(R::table_name()),
+        defs.join(",\n")
+    )
+}
+
+// INSERT statement with numbered placeholders (`$1`, `$2`, ...) that
+// overwrites the row when its primary key already exists.
+pub fn upsert_sql<R: Table>() -> String {
+    let columns = R::columns();
+    let names = columns.iter().map(|c| c.name).collect::<Vec<&str>>();
+    let placeholders = (1..=columns.len())
+        .map(|i| format!("${}", i))
+        .collect::<Vec<String>>();
+    format!(
+        "INSERT INTO {} ({}) VALUES ({}) {}",
+        quote_ident(R::table_name()),
+        quote_idents(&names),
+        placeholders.join(", "),
+        on_conflict_sql::<R>(),
+    )
+}
+
+pub fn on_conflict_sql<R: Table>() -> String {
+    let primary_key = R::primary_key();
+    let updates = R::columns()
+        .iter()
+        .filter(|c| !primary_key.contains(&c.name))
+        .map(|c| format!("{0} = excluded.{0}", quote_ident(c.name)))
+        .collect::<Vec<String>>();
+    format!(
+        "ON CONFLICT ({}) DO UPDATE SET {}",
+        quote_idents(primary_key),
+        updates.join(", ")
+    )
+}
+
+// BigQuery table schema, as taken by `bq mk --schema`
+pub fn bigquery_schema<R: Table>() -> serde_json::Value {
+    let fields = R::columns()
+        .iter()
+        .map(|c| {
+            let ty = match c.ty {
+                ColumnType::Boolean => "BOOL",
+                ColumnType::Int32 | ColumnType::Int64 | ColumnType::UInt32 | ColumnType::UInt64 => {
+                    "INT64"
+                }
+                ColumnType::Text => "STRING",
+                ColumnType::Timestamp => "TIMESTAMP",
+                ColumnType::Json => "JSON",
+            };
+            serde_json::json!({
+                "name": c.name,
+                "type": ty,
+                "mode": if c.nullable { "NULLABLE" } else { "REQUIRED" },
+            })
+        })
+        .collect();
+    serde_json::Value::Array(fields)
+}
+
+pub fn markdown_columns<R: Table>() -> String {
+    let primary_key = R::primary_key();
+    let mut doc = format!(
+        "## {}\n\n| Column | Type | Nullable | Rust type |\n|---|---|---|---|\n",
+        R::table_name()
+    );
+    for c in R::columns() {
+        let name = if primary_key.contains(&c.name) {
+            format!("**{}**", c.name)
+        } else {
+            c.name.to_string()
+        };
+        doc.push_str(&format!(
+            "| {} | {:?} | {} | `{}` |\n",
+            name,
+            c.ty,
+            if c.nullable { "yes" } else { "no" },
+            c.rust_type
+        ));
+    }
+    doc
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum SchemaFormat {
+    Postgres,
+    Sqlite,
+    Bigquery,
+    Markdown,
+}
+
+impl SchemaFormat {
+    pub fn render<R: Table>(&self) -> String {
+        match self {
+            SchemaFormat::Postgres => format!("{};\n", create_table_sql::<R>(Dialect::Postgres)),
+            SchemaFormat::Sqlite => format!("{};\n", create_table_sql::<R>(Dialect::Sqlite)),
+            SchemaFormat::Bigquery => {
+                format!("{:#}\n", bigquery_schema::<R>())
+            }
+            SchemaFormat::Markdown => markdown_columns::<R>(),
+        }
+    }
+}
+
+impl FromStr for SchemaFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "postgres" | "postgresql" => Ok(SchemaFormat::Postgres),
+            "sqlite" => Ok(SchemaFormat::Sqlite),
+            "bigquery" => Ok(SchemaFormat::Bigquery),
+            "markdown" | "md" => Ok(SchemaFormat::Markdown),
+            _ => Err(format!("unknown schema format: {}", s)),
+        }
+    }
+}
+
+// Defines a record struct and implements `Table` from its field list, so the
+// columns always follow the struct definition. The leading `#[table(...)]`
+// names the table, its primary key, which defaults to `id`, and optionally
+// the field holding the watermark.
+// Fields must not be renamed with `#[serde(rename)]`, as the column names
+// are taken from the fields.
+macro_rules! record {
+    (
+        #[table(
+            name = $table:literal
+            $(, primary_key = [$($key:literal),+])?
+            $(, watermark = $watermark:ident)?
+        )]
+        $(#[$attr:meta])*
+        pub struct $name:ident {
+            $(
+                $(#[$field_attr:meta])*
+                pub $field:ident: $ty:ty,
+            )*
+        }
+    ) => {
+        $(#[$attr])*
+        pub struct $name {
+            $(
+                $(#[$field_attr])*
+                pub $field: $ty,
+            )*
+        }
+
+        impl $crate::schema::Table for $name {
+            fn table_name() -> &'static str {
+                $table
+            }
+
+            fn columns() -> Vec<$crate::schema::Column> {
+                vec![$($crate::schema::Column::of::<$ty>(stringify!($field), stringify!($ty))),*]
+            }
+
+            $(
+                fn primary_key() -> &'static [&'static str] {
+                    &[$($key),+]
+                }
+            )?
+
+            $(
+                fn watermark(&self) -> Option<chrono::DateTime<chrono::Utc>> {
+                    $crate::schema::Timestamp::timestamp(&self.$watermark)
+                }
+            )?
+        }
+    };
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::issues::IssueRec;
+    use crate::users::UserRec;
+    use octocrab::models::issues::Issue;
+
+    #[test]
+    fn test_columns_follow_serialized_record() -> Result<(), Box<dyn std::error::Error>> {
+        let model: Issue = serde_json::from_str(include_str!("../testdata/issue.json"))?;
+        let record: IssueRec = model.into();
+
+        let serialized = serde_json::to_value(&record)?;
+        let keys: Vec<&str> = serialized
+            .as_object()
+            .unwrap()
+            .keys()
+            .map(|k| k.as_str())
+            .collect();
+        let columns = IssueRec::columns();
+        assert_eq!(columns.iter().map(|c| c.name).collect::<Vec<_>>(), keys);
+
+        let column = |name| columns.iter().find(|c| c.name == name).unwrap();
+        assert_eq!(column("id").ty, ColumnType::Int64);
+        assert!(!column("id").nullable);
+        assert_eq!(column("labels").ty, ColumnType::Json);
+        assert_eq!(column("closed_at").ty, ColumnType::Timestamp);
+        assert!(column("closed_at").nullable);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_sqlite_ddl() {
+        use crate::commits::CommitRec;
+
+        let ddl = create_table_sql::<CommitRec>(Dialect::Sqlite);
+        // The key is never NULL, although the field is an Option
+        assert!(ddl
+            .starts_with("CREATE TABLE IF NOT EXISTS \"commits\" (\n    \"sha\" TEXT NOT NULL,\n"));
+        assert!(ddl.contains("    \"comment_count\" INTEGER NOT NULL,\n"));
+        assert!(ddl.ends_with("    PRIMARY KEY (\"sha\")\n)"));
+
+        let upsert = upsert_sql::<CommitRec>();
+        assert!(upsert
+            .contains("ON CONFLICT (\"sha\") DO UPDATE SET \"node_id\" = excluded.\"node_id\","));
+    }
+
+    #[test]
+    fn test_postgres_ddl() {
+        let ddl = create_table_sql::<IssueRec>(Dialect::Postgres);
+        assert!(ddl.contains("    \"id\" BIGINT NOT NULL,\n"));
+        assert!(ddl.contains("    \"labels\" JSONB NOT NULL,\n"));
+        assert!(ddl.contains("    \"closed_at\" TIMESTAMPTZ,\n"));
+    }
+
+    #[test]
+    fn test_reserved_word_column() {
+        use crate::labels::LabelRec;
+
+        let ddl = create_table_sql::<LabelRec>(Dialect::Postgres);
+        assert!(ddl.contains("    \"default\" BOOLEAN NOT NULL,\n"));
+        let ddl = create_table_sql::<LabelRec>(Dialect::Sqlite);
+        assert!(ddl.contains("    \"default\" INTEGER NOT NULL,\n"));
+        assert!(upsert_sql::<LabelRec>().contains("\"default\" = excluded.\"default\""));
+    }
+
+    #[test]
+    fn test_bigquery_schema() {
+        use crate::events::EventRec;
+
+        let schema = bigquery_schema::<EventRec>();
+        let actor_id = schema
+            .as_array()
+            .unwrap()
+            .iter()
+            .find(|f| f["name"] == "actor_id")
+            .unwrap();
+        assert_eq!(
+            actor_id,
+            &serde_json::json!({"name": "actor_id", "type": "INT64", "mode": "NULLABLE"})
+        );
+    }
+
+    #[test]
+    fn test_markdown_columns() {
+        use crate::events::EventRec;
+
+        let doc = markdown_columns::<EventRec>();
+        assert!(doc.starts_with("## events\n"));
+        assert!(doc.contains("| **id** | Int64 | yes | `Option<i64>` |\n"));
+        assert!(doc.contains("| created_at | Timestamp | no | `DateTime` |\n"));
+    }
+
+    #[test]
+    fn test_raw_identifier_column_name() {
+        assert!(UserRec::columns().iter().any(|c| c.name == "type"));
+    }
+}
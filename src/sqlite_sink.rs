@@ -0,0 +1,143 @@
+use std::path::Path;
+
+use rusqlite::types::Value as SqlValue;
+use rusqlite::{params_from_iter, Connection};
+use serde::Serialize;
+use serde_json::Value;
+
+use crate::schema::{create_table_sql, quote_ident, upsert_sql, Column, Dialect, Table};
+use crate::RecordSink;
+
+// Upserts records into a table named after the record type, so repeated
+// extractions over overlapping windows keep one row per primary key.
+// Writes are grouped into transactions of `batch_size` records.
+pub struct SqliteSink {
+    conn: Connection,
+    columns: Vec<Column>,
+    upsert: String,
+    pending: usize,
+    batch_size: usize,
+}
+
+impl SqliteSink {
+    pub fn open<R: Table>(path: impl AsRef<Path>) -> anyhow::Result<Self> {
+        Self::new::<R>(Connection::open(path)?)
+    }
+
+    pub fn new<R: Table>(conn: Connection) -> anyhow::Result<Self> {
+        conn.execute_batch(&create_table_sql::<R>(Dialect::Sqlite))?;
+        add_missing_columns::<R>(&conn)?;
+        Ok(Self {
+            conn,
+            columns: R::columns(),
+            upsert: upsert_sql::<R>(),
+            pending: 0,
+            batch_size: 100,
+        })
+    }
+
+    pub fn batch_size(mut self, batch_size: usize) -> Self {
+        self.batch_size = batch_size;
+        self
+    }
+
+    pub fn connection(&self) -> &Connection {
+        &self.conn
+    }
+
+    fn commit(&mut self) -> anyhow::Result<()> {
+        if self.pending > 0 {
+            self.conn.execute_batch("COMMIT")?;
+            self.pending = 0;
+        }
+        Ok(())
+    }
+}
+
+// Columns added to a record after the table was created
+fn add_missing_columns<R: Table>(conn: &Connection) -> anyhow::Result<()> {
+    let table = quote_ident(R::table_name());
+    let mut stmt = conn.prepare(&format!("PRAGMA table_info({})", table))?;
+    let existing = stmt
+        .query_map([], |row| row.get::<_, String>(1))?
+        .collect::<rusqlite::Result<Vec<String>>>()?;
+    for column in R::columns() {
+        if !existing.iter().any(|name| name == column.name) {
+            conn.execute_batch(&format!(
+                "ALTER TABLE {} ADD COLUMN {} {}",
+                table,
+                quote_ident(column.name),
+                Dialect::Sqlite.sql_type(column.ty),
+            ))?;
+        }
+    }
+    Ok(())
+}
+
+fn to_sql_value(value: Option<&Value>) -> SqlValue {
+    match value {
+        None | Some(Value::Null) => SqlValue::Null,
+        Some(Value::Bool(b)) => SqlValue::Integer(*b as i64),
+        Some(Value::Number(n)) => match n.as_i64() {
+            Some(i) => SqlValue::Integer(i),
+            None => SqlValue::Real(n.as_f64().unwrap_or_default()),
+        },
+        Some(Value::String(s)) => SqlValue::Text(s.clone()),
+        Some(other) => SqlValue::Text(other.to_string()),
+    }
+}
+
+impl<R: Serialize> RecordSink<R> for SqliteSink {
+    fn write(&mut self, record: R) -> anyhow::Result<()> {
+        let row = match serde_json::to_value(&record)? {
+            Value::Object(row) => row,
+            _ => anyhow::bail!("record must be a struct"),
+        };
+        if self.pending == 0 {
+            self.conn.execute_batch("BEGIN")?;
+        }
+        let values = self.columns.iter().map(|c| to_sql_value(row.get(c.name)));
+        self.conn
+            .prepare_cached(&self.upsert)?
+            .execute(params_from_iter(values))?;
+        self.pending += 1;
+        if self.pending >= self.batch_size {
+            self.commit()?;
+        }
+        Ok(())
+    }
+
+    fn flush(&mut self) -> anyhow::Result<()> {
+        self.commit()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::issues::IssueRec;
+    use octocrab::models::issues::Issue;
+
+    #[test]
+    fn test_upsert_by_primary_key() -> Result<(), Box<dyn std::error::Error>> {
+        let mut sink = SqliteSink::new::<IssueRec>(Connection::open_in_memory()?)?;
+        for title in ["Found a bug", "Found a bug (edited)"].iter() {
+            let model: Issue = serde_json::from_str(include_str!("../testdata/issue.json"))?;
+            let mut record: IssueRec = model.into();
+            record.title = title.to_string();
+            sink.write(record)?;
+        }
+        RecordSink::<IssueRec>::close(&mut sink)?;
+
+        let (count, title, labels): (i64, String, String) = sink.connection().query_row(
+            "SELECT COUNT(*), MAX(title), MAX(labels) FROM issues",
+            [],
+            |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?)),
+        )?;
+        assert_eq!(count, 1);
+        assert_eq!(title, "Found a bug (edited)");
+        assert_eq!(labels, "[\"bug\",\"security\",\"needs review\"]");
+
+        Ok(())
+    }
+}
@@ -0,0 +1,131 @@
+use std::fs;
+use std::io;
+use std::path::PathBuf;
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+
+use crate::schema::Table;
+use crate::RecordSink;
+
+#[derive(Serialize, Deserialize, Debug)]
+struct Watermark {
+    since: DateTime<Utc>,
+}
+
+// Watermarks of incremental syncs, kept in one file per repository and
+// target: `<root>/<owner>/<name>/<target>.json`.
+pub struct StateDir {
+    root: PathBuf,
+}
+
+impl StateDir {
+    pub fn new(root: impl Into<PathBuf>) -> Self {
+        Self { root: root.into() }
+    }
+
+    fn path(&self, repository: &str, target: &str) -> PathBuf {
+        self.root.join(repository).join(format!("{}.json", target))
+    }
+
+    pub fn load(&self, repository: &str, target: &str) -> anyhow::Result<Option<DateTime<Utc>>> {
+        match fs::read(self.path(repository, target)) {
+            Ok(bytes) => Ok(Some(serde_json::from_slice::<Watermark>(&bytes)?.since)),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(None),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    // Keeps the stored watermark if it is later than `since`, so that a
+    // backfill over an older window does not move the next sync back.
+    pub fn save(&self, repository: &str, target: &str, since: DateTime<Utc>) -> anyhow::Result<()> {
+        let since = match self.load(repository, target)? {
+            Some(stored) if stored > since => stored,
+            _ => since,
+        };
+        let path = self.path(repository, target);
+        fs::create_dir_all(path.parent().unwrap())?;
+        let tmp = path.with_extension("tmp");
+        fs::write(&tmp, serde_json::to_vec(&Watermark { since })?)?;
+        fs::rename(&tmp, &path)?;
+        Ok(())
+    }
+}
+
+// Passes records on to `inner`, keeping the latest watermark among them.
+pub struct WatermarkSink<S> {
+    inner: S,
+    latest: Option<DateTime<Utc>>,
+}
+
+impl<S> WatermarkSink<S> {
+    pub fn new(inner: S) -> Self {
+        Self {
+            inner,
+            latest: None,
+        }
+    }
+
+    pub fn latest(&self) -> Option<DateTime<Utc>> {
+        self.latest
+    }
+}
+
+impl<R: Table, S: RecordSink<R>> RecordSink<R> for WatermarkSink<S> {
+    fn write(&mut self, record: R) -> anyhow::Result<()> {
+        let watermark = record.watermark();
+        self.inner.write(record)?;
+        self.latest = self.latest.max(watermark);
+        Ok(())
+    }
+
+    fn flush(&mut self) -> anyhow::Result<()> {
+        self.inner.flush()
+    }
+
+    fn close(&mut self) -> anyhow::Result<()> {
+        self.inner.close()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::issues::IssueRec;
+    use octocrab::models::issues::Issue;
+
+    #[test]
+    fn test_watermark_per_repository_and_target() -> Result<(), Box<dyn std::error::Error>> {
+        let root = std::env::temp_dir().join(format!("octx-test-state-{}", std::process::id()));
+        let state = StateDir::new(&root);
+        let older = "2011-04-22T13:33:48Z".parse::<DateTime<Utc>>()?;
+        let newer = "2011-04-23T13:33:48Z".parse::<DateTime<Utc>>()?;
+
+        assert_eq!(state.load("octocat/Hello-World", "issues")?, None);
+        state.save("octocat/Hello-World", "issues", newer)?;
+        state.save("octocat/Hello-World", "issues", older)?;
+        state.save("octocat/Hello-World", "comments", older)?;
+        assert_eq!(state.load("octocat/Hello-World", "issues")?, Some(newer));
+        assert_eq!(state.load("octocat/Hello-World", "comments")?, Some(older));
+        assert_eq!(state.load("octocat/Spoon-Knife", "issues")?, None);
+
+        fs::remove_dir_all(&root)?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_sink_tracks_latest_watermark() -> Result<(), Box<dyn std::error::Error>> {
+        let mut sink = WatermarkSink::new(vec![]);
+        for day in [22, 24, 23].iter() {
+            let model: Issue = serde_json::from_str(include_str!("../testdata/issue.json"))?;
+            let mut record: IssueRec = model.into();
+            record.updated_at = format!("2011-04-{}T13:33:48Z", day).parse()?;
+            sink.write(record)?;
+        }
+        assert_eq!(
+            sink.latest(),
+            Some("2011-04-24T13:33:48Z".parse::<DateTime<Utc>>()?)
+        );
+        Ok(())
+    }
+}
@@ -0,0 +1,195 @@
+use futures::{pin_mut, Stream, TryStreamExt};
+use octocrab::models::Author;
+use serde::*;
+use url::Url;
+
+use crate::checkpoint::Cursor;
+use crate::commits::CommitWalker;
+use crate::*;
+
+type DateTime = chrono::DateTime<chrono::Utc>;
+
+// ref: https://docs.github.com/en/rest/commits/statuses
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[non_exhaustive]
+pub struct Status {
+    pub id: i64,
+    pub node_id: String,
+    pub url: Url,
+    pub state: String,
+    pub description: Option<String>,
+    pub target_url: Option<String>,
+    pub context: String,
+    pub creator: Option<Author>,
+    pub created_at: DateTime,
+    pub updated_at: DateTime,
+}
+
+record! {
+    #[table(name = "statuses")]
+    #[derive(Serialize, Debug)]
+    pub struct StatusRec {
+        pub id: i64,
+        pub node_id: String,
+        pub url: Url,
+        pub sha: String,
+        pub context: String,
+        pub state: String,
+        pub description: Option<String>,
+        pub target_url: Option<String>,
+        pub creator_id: Option<i64>,
+        pub created_at: DateTime,
+        pub updated_at: DateTime,
+
+        pub sdc_repository: String,
+    }
+}
+
+impl RepositryAware for StatusRec {
+    fn set_repository(&mut self, name: String) {
+        self.sdc_repository = name;
+    }
+}
+
+impl From<Status> for StatusRec {
+    fn from(from: Status) -> Self {
+        Self {
+            id: from.id,
+            node_id: from.node_id,
+            url: from.url,
+            sha: String::default(),
+            context: from.context,
+            state: from.state,
+            description: from.description,
+            target_url: from.target_url,
+            creator_id: from.creator.map(|u| u.id.0 as i64),
+            created_at: from.created_at,
+            updated_at: from.updated_at,
+
+            sdc_repository: String::default(),
+        }
+    }
+}
+
+// Walks the commits of the default branch since `since` and lists every
+// status set on each, not only the latest of each context
+pub struct StatusFetcher {
+    owner: String,
+    name: String,
+    octocrab: octocrab::Octocrab,
+    commits: CommitWalker,
+}
+
+impl StatusFetcher {
+    pub fn new(
+        owner: String,
+        name: String,
+        since: Option<DateTime>,
+        octocrab: octocrab::Octocrab,
+    ) -> Self {
+        Self {
+            commits: CommitWalker::new(owner.clone(), name.clone(), since, octocrab.clone()),
+            owner,
+            name,
+            octocrab,
+        }
+    }
+
+    pub fn with_cursor(mut self, cursor: Cursor) -> Self {
+        self.commits = self.commits.with_cursor(cursor);
+        self
+    }
+
+    // Commits whose statuses are fetched at a time
+    pub fn with_concurrency(mut self, concurrency: usize) -> Self {
+        self.commits = self.commits.with_concurrency(concurrency);
+        self
+    }
+
+    // Log and leave out a commit whose statuses cannot be fetched instead of
+    // failing
+    pub fn with_skip_errors(mut self, yes: bool) -> Self {
+        self.commits = self.commits.with_skip_errors(yes);
+        self
+    }
+
+    fn reponame(&self) -> String {
+        format!("{}/{}", self.owner, self.name)
+    }
+
+    fn entrypoint_route(&self, sha: &str) -> crate::Result<String> {
+        let param = Params::default();
+
+        Ok(format!(
+            "/repos/{owner}/{repo}/commits/{sha}/statuses?{query}",
+            owner = &self.owner,
+            repo = &self.name,
+            sha = sha,
+            query = param.to_query()?,
+        ))
+    }
+
+    async fn commit_statuses(&self, sha: String) -> crate::Result<Vec<StatusRec>> {
+        let pages = pages::<Status>(&self.octocrab, self.entrypoint_route(&sha)?);
+        pin_mut!(pages);
+        let mut statuses = vec![];
+        while let Some(mut page) = pages.try_next().await? {
+            for status in page.take_items() {
+                let mut status: StatusRec = status.into();
+                status.sha = sha.clone();
+                status.set_repository(self.reponame());
+                statuses.push(status);
+            }
+        }
+        Ok(statuses)
+    }
+
+    pub fn stream(&self) -> impl Stream<Item = crate::Result<StatusRec>> + '_ {
+        self.commits
+            .walk("statuses", move |sha| self.commit_statuses(sha))
+    }
+
+    pub async fn fetch<S: RecordSink<StatusRec> + ?Sized>(
+        &self,
+        sink: &mut S,
+    ) -> crate::Result<()> {
+        write_stream(self.stream(), sink).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_convert_status_model() -> Result<(), Box<dyn std::error::Error>> {
+        let model: Status = serde_json::from_str(
+            r#"{
+                "url": "https://api.github.com/repos/octocat/Hello-World/statuses/6dcb09b5",
+                "avatar_url": "https://github.com/images/error/hubot_happy.gif",
+                "id": 1,
+                "node_id": "MDY6U3RhdHVzMQ==",
+                "state": "success",
+                "description": "Build has completed successfully",
+                "target_url": "https://ci.example.com/1000/output",
+                "context": "continuous-integration/jenkins",
+                "created_at": "2012-07-20T01:19:13Z",
+                "updated_at": "2012-07-20T01:19:13Z",
+                "creator": null
+            }"#,
+        )?;
+
+        let record: StatusRec = model.into();
+
+        assert_eq!(record.id, 1);
+        assert_eq!(record.context, "continuous-integration/jenkins".to_string());
+        assert_eq!(record.state, "success".to_string());
+        assert_eq!(
+            record.target_url.as_deref(),
+            Some("https://ci.example.com/1000/output")
+        );
+        assert_eq!(record.creator_id, None);
+
+        Ok(())
+    }
+}
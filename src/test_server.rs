@@ -0,0 +1,94 @@
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::Duration;
+
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::TcpListener;
+
+use crate::client::{build, Credentials, RateLimiter, RetryPolicy};
+
+// Serves `routes` of (path, delay in milliseconds, JSON body), answering every
+// connection on its own so that a slow route does not hold back the others.
+// Query strings are ignored and unknown paths are 404.
+pub async fn serve(routes: Vec<(String, u64, String)>) -> octocrab::Octocrab {
+    let routes = routes
+        .into_iter()
+        .map(|(path, delay, body)| (path, (delay, body)))
+        .collect::<HashMap<String, (u64, String)>>();
+    let routes = Arc::new(routes);
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    tokio::spawn(async move {
+        loop {
+            let (mut socket, _) = listener.accept().await.unwrap();
+            let routes = routes.clone();
+            tokio::spawn(async move {
+                let mut buf = [0; 4096];
+                let len = socket.read(&mut buf).await.unwrap();
+                let request = String::from_utf8_lossy(&buf[..len]);
+                let target = request.split_whitespace().nth(1).unwrap_or_default();
+                let path = target.split('?').next().unwrap_or_default();
+                let (status, body) = match routes.get(path) {
+                    Some((delay, body)) => {
+                        tokio::time::sleep(Duration::from_millis(*delay)).await;
+                        ("200 OK", body.as_str())
+                    }
+                    None => ("404 Not Found", "{\"message\": \"Not Found\"}"),
+                };
+                let response = format!(
+                    "HTTP/1.1 {}\r\nconnection: close\r\ncontent-type: application/json\r\n\
+                     content-length: {}\r\n\r\n{}",
+                    status,
+                    body.len(),
+                    body
+                );
+                socket.write_all(response.as_bytes()).await.unwrap();
+                socket.shutdown().await.unwrap();
+            });
+        }
+    });
+
+    let url = format!("http://{}/", addr);
+    let credentials = Credentials::Token("x".to_string());
+    build(
+        &url,
+        credentials,
+        RateLimiter::new(),
+        RetryPolicy::default(),
+    )
+    .unwrap()
+}
+
+// Lists pull requests `numbers` of octocat/Hello-World and serves `path`
+// under each of them with `body`, the earlier pull requests taking longer to
+// respond
+pub async fn serve_pulls(
+    numbers: &[u64],
+    path: &str,
+    body: impl Fn(u64) -> serde_json::Value,
+) -> octocrab::Octocrab {
+    let listing = numbers
+        .iter()
+        .map(|n| {
+            serde_json::json!({
+                "url": format!("https://api.github.com/repos/octocat/Hello-World/pulls/{}", n),
+                "id": n,
+                "number": n,
+                "node_id": "MDExOlB1bGxSZXF1ZXN0MQ==",
+                "created_at": "2021-01-01T00:00:00Z",
+                "updated_at": "2021-01-01T00:00:00Z",
+            })
+        })
+        .collect::<Vec<serde_json::Value>>();
+    let mut routes = vec![(
+        "/repos/octocat/Hello-World/pulls".to_string(),
+        0,
+        serde_json::Value::from(listing).to_string(),
+    )];
+    for (i, n) in numbers.iter().enumerate() {
+        let route = format!("/repos/octocat/Hello-World/pulls/{}{}", n, path);
+        let delay = 100 * (numbers.len() - i) as u64;
+        routes.push((route, delay, body(*n).to_string()));
+    }
+    serve(routes).await
+}
@@ -0,0 +1,334 @@
+use async_stream::try_stream;
+use futures::{pin_mut, stream, Stream, StreamExt, TryFutureExt, TryStreamExt};
+use octocrab::models::Author;
+use serde::*;
+
+use crate::checkpoint::Cursor;
+use crate::commits::GitUser;
+use crate::error::skip_failed;
+use crate::events::{Label, MilestonePartial};
+use crate::*;
+
+type DateTime = chrono::DateTime<chrono::Utc>;
+
+// Only the number is needed to walk the timeline of an issue, and when it was
+// updated to tell how far the walk got
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+pub struct IssueNumber {
+    pub number: u64,
+    pub updated_at: DateTime,
+}
+
+// The issue a cross-referenced item comes from
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SourceIssue {
+    pub id: i64,
+    pub number: u64,
+    pub repository: Option<SourceRepository>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SourceRepository {
+    pub full_name: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Source {
+    pub issue: Option<SourceIssue>,
+}
+
+// Timeline items differ by event in which fields they have, so every field
+// is optional as in `events::IssueEvent`. Commits have a sha and a git author
+// instead of an id, an actor and created_at, and reviews have submitted_at.
+// ref: https://docs.github.com/en/rest/issues/timeline
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[non_exhaustive]
+pub struct TimelineEvent {
+    pub id: Option<i64>,
+    pub node_id: Option<String>,
+    pub url: Option<String>,
+    pub event: Option<String>,
+    pub actor: Option<Author>,
+    // Instead of actor for commented and reviewed
+    pub user: Option<Author>,
+    pub commit_id: Option<String>,
+    // Instead of commit_id for committed
+    pub sha: Option<String>,
+    pub author: Option<GitUser>,
+    pub label: Option<Label>,
+    pub milestone: Option<MilestonePartial>,
+    pub assignee: Option<Author>,
+    pub requested_reviewer: Option<Author>,
+    pub source: Option<Source>,
+    // Of a review
+    pub state: Option<String>,
+    pub state_reason: Option<String>,
+    pub created_at: Option<DateTime>,
+    pub submitted_at: Option<DateTime>,
+}
+
+record! {
+    #[table(
+        name = "timeline",
+        primary_key = ["sdc_repository", "issue_number", "key"],
+        watermark = issue_updated_at
+    )]
+    #[derive(Serialize, Debug)]
+    pub struct TimelineRec {
+        // The id, or the sha of a commit, or the event, time and source for
+        // items having neither
+        pub key: String,
+        pub id: Option<i64>,
+        pub node_id: Option<String>,
+        pub url: Option<String>,
+        pub event: Option<String>,
+        pub actor_id: Option<i64>,
+        pub commit_id: Option<String>,
+        pub label: Option<String>,
+        pub milestone_title: Option<String>,
+        pub assignee_id: Option<i64>,
+        pub requested_reviewer_id: Option<i64>,
+        pub source_issue_id: Option<i64>,
+        pub source_issue_number: Option<u64>,
+        pub source_repository: Option<String>,
+        pub state: Option<String>,
+        pub state_reason: Option<String>,
+        pub created_at: Option<DateTime>,
+
+        pub issue_number: u64,
+        // Items are not updated themselves, and a commit's created_at is when
+        // it was authored, so the issue's is the watermark
+        pub issue_updated_at: DateTime,
+        pub sdc_repository: String,
+    }
+}
+
+impl RepositryAware for TimelineRec {
+    fn set_repository(&mut self, name: String) {
+        self.sdc_repository = name;
+    }
+}
+
+impl From<TimelineEvent> for TimelineRec {
+    fn from(from: TimelineEvent) -> Self {
+        let source_issue = from.source.and_then(|s| s.issue);
+        let commit_id = from.commit_id.or(from.sha);
+        let created_at = from
+            .created_at
+            .or(from.submitted_at)
+            .or(from.author.map(|a| a.date));
+        let key = match (from.id, &commit_id) {
+            (Some(id), _) => id.to_string(),
+            (None, Some(sha)) => sha.clone(),
+            (None, None) => format!(
+                "{}:{}:{}",
+                from.event.as_deref().unwrap_or_default(),
+                created_at.map(|t| t.to_rfc3339()).unwrap_or_default(),
+                source_issue.as_ref().map(|i| i.id).unwrap_or_default(),
+            ),
+        };
+
+        Self {
+            key,
+            id: from.id,
+            node_id: from.node_id,
+            url: from.url,
+            event: from.event,
+            actor_id: from.actor.or(from.user).map(|u| u.id.0 as i64),
+            commit_id,
+            label: from.label.map(|l| l.name),
+            milestone_title: from.milestone.map(|m| m.title),
+            assignee_id: from.assignee.map(|u| u.id.0 as i64),
+            requested_reviewer_id: from.requested_reviewer.map(|u| u.id.0 as i64),
+            source_issue_id: source_issue.as_ref().map(|i| i.id),
+            source_issue_number: source_issue.as_ref().map(|i| i.number),
+            source_repository: source_issue.and_then(|i| i.repository).map(|r| r.full_name),
+            state: from.state,
+            state_reason: from.state_reason,
+            created_at,
+
+            issue_number: 0,
+            issue_updated_at: DateTime::default(),
+            sdc_repository: String::default(),
+        }
+    }
+}
+
+// Walks the timeline of every issue and pull request updated since `since`,
+// `concurrency` issues at a time.
+pub struct TimelineFetcher {
+    owner: String,
+    name: String,
+    since: Option<DateTime>,
+    octocrab: octocrab::Octocrab,
+    cursor: Cursor,
+    concurrency: usize,
+    skip_errors: bool,
+}
+
+impl TimelineFetcher {
+    pub fn new(
+        owner: String,
+        name: String,
+        since: Option<DateTime>,
+        octocrab: octocrab::Octocrab,
+    ) -> Self {
+        Self {
+            owner,
+            name,
+            since,
+            octocrab,
+            cursor: Cursor::default(),
+            concurrency: PULL_CONCURRENCY,
+            skip_errors: false,
+        }
+    }
+
+    pub fn with_cursor(mut self, cursor: Cursor) -> Self {
+        self.cursor = cursor;
+        self
+    }
+
+    // Issues of a page whose timelines are fetched at a time
+    pub fn with_concurrency(mut self, concurrency: usize) -> Self {
+        self.concurrency = concurrency.max(1);
+        self
+    }
+
+    // Log and leave out an issue whose timeline cannot be fetched instead of
+    // failing
+    pub fn with_skip_errors(mut self, yes: bool) -> Self {
+        self.skip_errors = yes;
+        self
+    }
+}
+
+impl UrlConstructor for TimelineFetcher {
+    fn reponame(&self) -> String {
+        format!("{}/{}", self.owner, self.name)
+    }
+
+    fn entrypoint_route(&self) -> crate::Result<String> {
+        let param = Params {
+            state: octocrab::params::State::All.into(),
+            since: self.since,
+            ..Default::default()
+        };
+
+        Ok(format!(
+            "/repos/{owner}/{repo}/issues?{query}",
+            owner = &self.owner,
+            repo = &self.name,
+            query = param.to_query()?,
+        ))
+    }
+}
+
+impl TimelineFetcher {
+    async fn issue_timeline(&self, issue: &IssueNumber) -> crate::Result<Vec<TimelineRec>> {
+        let query = Params::default().to_query()?;
+        let route = format!(
+            "/repos/{owner}/{repo}/issues/{number}/timeline?{query}",
+            owner = &self.owner,
+            repo = &self.name,
+            number = issue.number,
+            query = query,
+        );
+        let pages = pages::<TimelineEvent>(&self.octocrab, route);
+        pin_mut!(pages);
+        let mut timeline = vec![];
+        while let Some(mut page) = pages.try_next().await? {
+            for event in page.take_items() {
+                let mut event: TimelineRec = event.into();
+                event.issue_number = issue.number;
+                event.issue_updated_at = issue.updated_at;
+                event.set_repository(self.reponame());
+                timeline.push(event);
+            }
+        }
+        Ok(timeline)
+    }
+
+    pub fn stream(&self) -> impl Stream<Item = crate::Result<TimelineRec>> + '_ {
+        try_stream! {
+            let route = self.entrypoint_route()?;
+            let issues = self.cursor.pages::<IssueNumber>(&self.octocrab, route);
+            pin_mut!(issues);
+            while let Some(mut page) = issues.try_next().await? {
+                let issues: Vec<IssueNumber> = page.take_items();
+                let timelines = stream::iter(issues.iter())
+                    .map(|issue| {
+                        let item = format!(
+                            "timeline of issue {}#{}",
+                            self.reponame(),
+                            issue.number
+                        );
+                        self.issue_timeline(issue)
+                            .map_err(|e| Error::item(item, e))
+                    })
+                    .buffered(self.concurrency);
+                pin_mut!(timelines);
+                while let Some(timeline) = timelines.next().await {
+                    for event in skip_failed(timeline, self.skip_errors)? {
+                        yield event;
+                    }
+                }
+            }
+        }
+    }
+
+    pub async fn fetch<S: RecordSink<TimelineRec> + ?Sized>(
+        &self,
+        sink: &mut S,
+    ) -> crate::Result<()> {
+        write_stream(self.stream(), sink).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::schema::Table;
+
+    #[test]
+    fn test_convert_timeline_items() -> Result<(), Box<dyn std::error::Error>> {
+        let items: Vec<TimelineEvent> = serde_json::from_str(
+            r#"[
+                {"id": 1, "event": "labeled", "label": {"name": "bug", "color": "f29513"},
+                 "created_at": "2011-04-14T16:00:49Z"},
+                {"sha": "6dcb09b5", "event": "committed",
+                 "author": {"name": "Monalisa", "email": "m@example.com",
+                            "date": "2011-04-14T16:00:49Z"}},
+                {"event": "cross-referenced", "created_at": "2011-04-14T16:00:49Z",
+                 "source": {"type": "issue", "issue": {"id": 7, "number": 3,
+                            "repository": {"full_name": "octocat/Spoon-Knife"}}}},
+                {"id": 2, "event": "closed", "state_reason": "not_planned",
+                 "created_at": "2011-04-14T16:00:49Z"}
+            ]"#,
+        )?;
+        let records: Vec<TimelineRec> = items.into_iter().map(TimelineRec::from).collect();
+
+        assert_eq!(records[0].key, "1");
+        assert_eq!(records[0].label.as_deref(), Some("bug"));
+        assert_eq!(records[1].key, "6dcb09b5");
+        assert_eq!(records[1].commit_id.as_deref(), Some("6dcb09b5"));
+        assert!(records[1].created_at.is_some());
+        assert_eq!(
+            records[2].key,
+            "cross-referenced:2011-04-14T16:00:49+00:00:7"
+        );
+        assert_eq!(records[2].source_issue_number, Some(3));
+        assert_eq!(
+            records[2].source_repository.as_deref(),
+            Some("octocat/Spoon-Knife")
+        );
+        assert_eq!(records[3].state_reason.as_deref(), Some("not_planned"));
+
+        // The watermark is the issue's updated_at, not the commit's date
+        let mut record = records.into_iter().nth(1).unwrap();
+        record.issue_updated_at = "2020-01-01T00:00:00Z".parse()?;
+        assert_eq!(record.watermark(), Some(record.issue_updated_at));
+
+        Ok(())
+    }
+}
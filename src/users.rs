@@ -2,28 +2,35 @@ use octocrab::models::Author as User;
 use url::Url;
 use serde::*;
 
+use async_stream::try_stream;
+use futures::{pin_mut, Stream, TryStreamExt};
+
+use crate::checkpoint::Cursor;
 use crate::*;
 
-#[derive(Serialize, Debug)]
-pub struct UserRec {
-    pub login: String,
-    pub id: i64,
-    pub node_id: String,
-    pub avatar_url: Url,
-    pub gravatar_id: String,
-    pub url: Url,
-    pub html_url: Url,
-    pub followers_url: Url,
-    pub following_url: Url,
-    pub gists_url: Url,
-    pub starred_url: Url,
-    pub subscriptions_url: Url,
-    pub organizations_url: Url,
-    pub repos_url: Url,
-    pub events_url: Url,
-    pub received_events_url: Url,
-    pub r#type: String,
-    pub site_admin: bool,
+record! {
+    #[table(name = "users")]
+    #[derive(Serialize, Debug)]
+    pub struct UserRec {
+        pub login: String,
+        pub id: i64,
+        pub node_id: String,
+        pub avatar_url: Url,
+        pub gravatar_id: String,
+        pub url: Url,
+        pub html_url: Url,
+        pub followers_url: Url,
+        pub following_url: Url,
+        pub gists_url: Url,
+        pub starred_url: Url,
+        pub subscriptions_url: Url,
+        pub organizations_url: Url,
+        pub repos_url: Url,
+        pub events_url: Url,
+        pub received_events_url: Url,
+        pub r#type: String,
+        pub site_admin: bool,
+    }
 }
 
 impl RepositryAware for UserRec {
@@ -57,11 +64,20 @@ impl From<User> for UserRec {
 
 pub struct UserFetcher {
     octocrab: octocrab::Octocrab,
+    cursor: Cursor,
 }
 
 impl UserFetcher {
     pub fn new(octocrab: octocrab::Octocrab) -> Self {
-        Self { octocrab }
+        Self {
+            octocrab,
+            cursor: Cursor::default(),
+        }
+    }
+
+    pub fn with_cursor(mut self, cursor: Cursor) -> Self {
+        self.cursor = cursor;
+        self
     }
 }
 
@@ -70,10 +86,10 @@ impl UrlConstructor for UserFetcher {
         "".to_string()
     }
 
-    fn entrypoint_route(&self) -> String {
+    fn entrypoint_route(&self) -> crate::Result<String> {
         let param = Params::default();
 
-        format!("/users?{query}", query = param.to_query())
+        Ok(format!("/users?{query}", query = param.to_query()?))
     }
 }
 
@@ -83,17 +99,19 @@ impl LoopWriter for UserFetcher {
 }
 
 impl UserFetcher {
-    pub async fn fetch<T: std::io::Write>(&self, mut wtr: csv::Writer<T>) -> octocrab::Result<()> {
-        let first: octocrab::Page<User> = self
-            .octocrab
-            .get(self.entrypoint_route(), None::<&()>)
-            .await?;
-        let mut next = self.write_and_continue(first, &mut wtr);
-
-        while let Some(page) = self.octocrab.get_page(&next).await? {
-            next = self.write_and_continue(page, &mut wtr);
+    pub fn stream(&self) -> impl Stream<Item = crate::Result<UserRec>> + '_ {
+        try_stream! {
+            let pages = self.cursor.pages::<User>(&self.octocrab, self.entrypoint_route()?);
+            pin_mut!(pages);
+            while let Some(mut page) = pages.try_next().await? {
+                for record in self.take_records(&mut page) {
+                    yield record;
+                }
+            }
         }
+    }
 
-        Ok(())
+    pub async fn fetch<S: RecordSink<UserRec> + ?Sized>(&self, sink: &mut S) -> crate::Result<()> {
+        write_stream(self.stream(), sink).await
     }
 }
@@ -1,93 +1,109 @@
-use octocrab::models::workflows::{Job, WorkFlow};
+use async_stream::try_stream;
+use futures::{pin_mut, Stream, TryStreamExt};
+use octocrab::models::workflows::{Job, Step, WorkFlow};
 use url::Url;
 use serde::Serialize;
 use std::ops::{Deref, DerefMut};
 
 use crate::api_ext::models::*;
+use crate::checkpoint::Cursor;
+use crate::error::skip_failed;
 use crate::*;
 
 type DateTime = chrono::DateTime<chrono::Utc>;
 
-#[derive(Serialize, Debug)]
-pub struct WorkFlowRec {
-    pub id: i64,
-    pub node_id: String,
-    pub name: String,
-    pub path: String,
-    pub state: String, // TODO: to_enum
-    pub created_at: DateTime,
-    pub updated_at: DateTime,
-    pub url: Url,
-    pub html_url: Url,
-    pub badge_url: Url,
-
-    pub sdc_repository: String,
+record! {
+    #[table(name = "workflows")]
+    #[derive(Serialize, Debug)]
+    pub struct WorkFlowRec {
+        pub id: i64,
+        pub node_id: String,
+        pub name: String,
+        pub path: String,
+        pub state: String, // TODO: to_enum
+        pub created_at: DateTime,
+        pub updated_at: DateTime,
+        pub url: Url,
+        pub html_url: Url,
+        pub badge_url: Url,
+
+        pub sdc_repository: String,
+    }
 }
 
-#[derive(Serialize, Debug)]
-pub struct RunRec {
-    pub id: i64,
-    pub workflow_id: i64,
-    pub node_id: String,
-    pub name: Option<String>,
-    pub head_branch: Option<String>,
-    pub head_sha: String,
-    pub run_number: i64,
-    pub event: String,  // TODO: to_enum
-    pub status: String, // TODO: to_enum
-    pub conclusion: Option<String>,
-    pub created_at: DateTime,
-    pub updated_at: DateTime,
-    pub url: Url,
-    pub html_url: Url,
-
-    pub sdc_repository: String,
+record! {
+    #[table(name = "runs", watermark = updated_at)]
+    #[derive(Serialize, Debug)]
+    pub struct RunRec {
+        pub id: i64,
+        pub workflow_id: i64,
+        pub node_id: String,
+        pub name: Option<String>,
+        pub head_branch: Option<String>,
+        pub head_sha: String,
+        pub run_number: i64,
+        pub event: String,  // TODO: to_enum
+        pub status: String, // TODO: to_enum
+        pub conclusion: Option<String>,
+        pub created_at: DateTime,
+        pub updated_at: DateTime,
+        pub url: Url,
+        pub html_url: Url,
+
+        pub sdc_repository: String,
+    }
 }
 
-#[derive(Serialize, Debug)]
-pub struct JobRec {
-    pub id: i64,
-    pub run_id: i64,
-    pub node_id: String,
-    pub head_sha: String,
-    pub status: String, // TODO: to_enum
-    pub conclusion: Option<String>,
-    pub started_at: DateTime,
-    pub completed_at: Option<DateTime>,
-    pub name: String,
-    pub url: Url,
-    pub html_url: Url,
-    pub run_url: Url,
-    pub check_run_url: String,
-    pub steps: String,
-
-    pub sdc_repository: String,
+record! {
+    #[table(name = "jobs")]
+    #[derive(Serialize, Debug)]
+    pub struct JobRec {
+        pub id: i64,
+        pub run_id: i64,
+        pub node_id: String,
+        pub head_sha: String,
+        pub status: String, // TODO: to_enum
+        pub conclusion: Option<String>,
+        pub started_at: DateTime,
+        pub completed_at: Option<DateTime>,
+        pub name: String,
+        pub url: Url,
+        pub html_url: Url,
+        pub run_url: Url,
+        pub check_run_url: String,
+        pub steps: Vec<Step>,
+
+        pub sdc_repository: String,
+    }
 }
 
-#[derive(Serialize, Debug)]
-pub struct JobStepRec {
-    pub job_id: i64,
-    pub run_id: i64,
-    pub job_node_id: String,
-    pub head_sha: String,
-    pub job_status: String, // TODO: to_enum
-    pub job_conclusion: Option<String>,
-    pub job_started_at: DateTime,
-    pub job_completed_at: Option<DateTime>,
-    pub job_name: String,
-    pub job_url: Url,
-    pub job_html_url: Url,
-    pub run_url: Url,
-    pub check_run_url: String,
-
-    pub name: String,
-    pub status: String, // TODO: to_enum
-    pub conclusion: Option<String>,
-    pub number: i64,
-    pub started_at: Option<DateTime>,
-    pub completed_at: Option<DateTime>,
-
-    pub sdc_repository: String,
+record! {
+    #[table(name = "job_steps", primary_key = ["job_id", "number"])]
+    #[derive(Serialize, Debug)]
+    pub struct JobStepRec {
+        pub job_id: i64,
+        pub run_id: i64,
+        pub job_node_id: String,
+        pub head_sha: String,
+        pub job_status: String, // TODO: to_enum
+        pub job_conclusion: Option<String>,
+        pub job_started_at: DateTime,
+        pub job_completed_at: Option<DateTime>,
+        pub job_name: String,
+        pub job_url: Url,
+        pub job_html_url: Url,
+        pub run_url: Url,
+        pub check_run_url: String,
+
+        pub name: String,
+        pub status: String, // TODO: to_enum
+        pub conclusion: Option<String>,
+        pub number: i64,
+        pub started_at: Option<DateTime>,
+        pub completed_at: Option<DateTime>,
+
+        pub sdc_repository: String,
+    }
 }
 
 #[derive(Serialize, Debug)]
@@ -149,7 +165,7 @@ impl From<Job> for JobRec {
             html_url: from.html_url,
             run_url: from.run_url,
             check_run_url: from.check_run_url,
-            steps: serde_json::to_string(&from.steps).unwrap_or("[]".to_string()),
+            steps: from.steps,
             sdc_repository: String::default(),
         }
     }
@@ -181,8 +197,8 @@ impl From<Job> for JobStepsRec {
                 head_sha: from.head_sha.clone(),
                 job_status: job_status.clone(),
                 job_conclusion: job_conclusion.clone(),
-                job_started_at: from.started_at.clone(),
-                job_completed_at: from.completed_at.clone(),
+                job_started_at: from.started_at,
+                job_completed_at: from.completed_at,
                 job_name: from.name.clone(),
                 job_url: from.url.clone(),
                 job_html_url: from.html_url.clone(),
@@ -233,6 +249,7 @@ pub struct WorkFlowFetcher {
     owner: String,
     name: String,
     octocrab: octocrab::Octocrab,
+    cursor: Cursor,
 }
 
 pub struct RunFetcher {
@@ -240,6 +257,7 @@ pub struct RunFetcher {
     name: String,
     since: Option<DateTime>,
     octocrab: octocrab::Octocrab,
+    cursor: Cursor,
 }
 
 pub struct JobFetcher {
@@ -247,6 +265,8 @@ pub struct JobFetcher {
     name: String,
     since: Option<DateTime>,
     pub octocrab: octocrab::Octocrab,
+    cursor: Cursor,
+    skip_errors: bool,
 }
 
 pub struct JobStepFetcher {
@@ -261,15 +281,15 @@ impl UrlConstructor for WorkFlowFetcher {
         format!("{}/{}", self.owner, self.name)
     }
 
-    fn entrypoint_route(&self) -> String {
+    fn entrypoint_route(&self) -> crate::Result<String> {
         let param = Params::default();
 
-        format!(
+        Ok(format!(
             "/repos/{owner}/{repo}/actions/workflows?{query}",
             owner = &self.owner,
             repo = &self.name,
-            query = param.to_query(),
-        )
+            query = param.to_query()?,
+        ))
     }
 }
 
@@ -278,16 +298,16 @@ impl UrlConstructor for JobStepFetcher {
         format!("{}/{}", self.owner, self.name)
     }
 
-    fn entrypoint_route(&self) -> String {
+    fn entrypoint_route(&self) -> crate::Result<String> {
         let param = Params::default();
 
-        format!(
+        Ok(format!(
             "/repos/{owner}/{repo}/actions/jobs/{job_id}?{query}",
             owner = &self.owner,
             repo = &self.name,
             job_id = &self.job_id,
-            query = param.to_query(),
-        )
+            query = param.to_query()?,
+        ))
     }
 }
 
@@ -307,21 +327,32 @@ impl WorkFlowFetcher {
             owner,
             name,
             octocrab,
+            cursor: Cursor::default(),
         }
     }
 
-    pub async fn fetch<T: std::io::Write>(&self, mut wtr: csv::Writer<T>) -> octocrab::Result<()> {
-        let first: octocrab::Page<WorkFlow> = self
-            .octocrab
-            .get(self.entrypoint_route(), None::<&()>)
-            .await?;
-        let mut next = self.write_and_continue(first, &mut wtr);
+    pub fn with_cursor(mut self, cursor: Cursor) -> Self {
+        self.cursor = cursor;
+        self
+    }
 
-        while let Some(page) = self.octocrab.get_page(&next).await? {
-            next = self.write_and_continue(page, &mut wtr);
+    pub fn stream(&self) -> impl Stream<Item = crate::Result<WorkFlowRec>> + '_ {
+        try_stream! {
+            let pages = self.cursor.pages::<WorkFlow>(&self.octocrab, self.entrypoint_route()?);
+            pin_mut!(pages);
+            while let Some(mut page) = pages.try_next().await? {
+                for record in self.take_records(&mut page) {
+                    yield record;
+                }
+            }
         }
+    }
 
-        Ok(())
+    pub async fn fetch<S: RecordSink<WorkFlowRec> + ?Sized>(
+        &self,
+        sink: &mut S,
+    ) -> crate::Result<()> {
+        write_stream(self.stream(), sink).await
     }
 }
 
@@ -337,82 +368,83 @@ impl RunFetcher {
             name,
             since,
             octocrab,
+            cursor: Cursor::default(),
         }
     }
 
+    pub fn with_cursor(mut self, cursor: Cursor) -> Self {
+        self.cursor = cursor;
+        self
+    }
+
     fn reponame(&self) -> String {
         format!("{}/{}", self.owner, self.name)
     }
 
-    fn entrypoint_route(&self, workflow_id: Option<String>) -> String {
+    fn entrypoint_route(&self, workflow_id: Option<String>) -> crate::Result<String> {
         let param = Params::default();
 
         if let Some(workflow_id_) = workflow_id {
-            format!(
+            Ok(format!(
                 "/repos/{owner}/{repo}/actions/workflows/{workflow_id}/runs?{query}",
                 owner = &self.owner,
                 repo = &self.name,
                 workflow_id = &workflow_id_,
-                query = param.to_query(),
-            )
+                query = param.to_query()?,
+            ))
         } else {
             // FIXME: no way to sort runs by updated_at
-            format!(
+            Ok(format!(
                 "/repos/{owner}/{repo}/actions/runs?{query}",
                 owner = &self.owner,
                 repo = &self.name,
-                query = param.to_query(),
-            )
+                query = param.to_query()?,
+            ))
         }
     }
 
-    fn write_and_continue<T: std::io::Write>(
-        &self,
-        mut page: octocrab::Page<Run>,
-        wtr: &mut csv::Writer<T>,
-    ) -> Option<http::Uri> {
-        let mut last_update: Option<DateTime> = None;
+    fn take_records(&self, page: &mut octocrab::Page<Run>) -> Vec<RunRec> {
         let labels: Vec<Run> = page.take_items();
-        for label in labels.into_iter() {
-            let mut label: RunRec = label.into();
-            label.set_repository(self.reponame());
-            wtr.serialize(&label).expect("Serialize failed");
+        labels
+            .into_iter()
+            .map(|label| {
+                let mut label: RunRec = label.into();
+                label.set_repository(self.reponame());
+                label
+            })
+            .collect()
+    }
 
-            last_update = Some(label.updated_at);
-        }
+    pub fn stream(
+        &self,
+        workflow_id: Option<String>,
+    ) -> impl Stream<Item = crate::Result<RunRec>> + '_ {
+        try_stream! {
+            let route = self.entrypoint_route(workflow_id)?;
+            let pages = self.cursor.pages::<Run>(&self.octocrab, route);
+            pin_mut!(pages);
+            while let Some(mut page) = pages.try_next().await? {
+                let mut last_update: Option<DateTime> = None;
+                for label in self.take_records(&mut page) {
+                    last_update = Some(label.updated_at);
+                    yield label;
+                }
 
-        if let Some(since) = self.since {
-            last_update.map_or_else(
-                || None,
-                |last| {
-                    if last < since {
-                        None
-                    } else {
-                        page.next.map(to_relative_uri)
+                if let Some(since) = self.since {
+                    if last_update.is_none_or(|last| last < since) {
+                        break;
                     }
-                },
-            )
-        } else {
-            page.next.map(to_relative_uri)
+                }
+            }
         }
     }
 
-    pub async fn fetch<T: std::io::Write>(
+    pub async fn fetch<S: RecordSink<RunRec> + ?Sized>(
         &self,
-        mut wtr: csv::Writer<T>,
+        sink: &mut S,
         workflow_id: Option<String>,
-    ) -> octocrab::Result<()> {
-        let first: octocrab::Page<Run> = self
-            .octocrab
-            .get(self.entrypoint_route(workflow_id), None::<&()>)
-            .await?;
-        let mut next = self.write_and_continue(first, &mut wtr);
-
-        while let Some(page) = self.octocrab.get_page(&next).await? {
-            next = self.write_and_continue(page, &mut wtr);
-        }
-
-        Ok(())
+    ) -> crate::Result<()> {
+        write_stream(self.stream(workflow_id), sink).await
     }
 }
 
@@ -428,112 +460,115 @@ impl JobFetcher {
             name,
             since,
             octocrab,
+            cursor: Cursor::default(),
+            skip_errors: false,
         }
     }
 
+    pub fn with_cursor(mut self, cursor: Cursor) -> Self {
+        self.cursor = cursor;
+        self
+    }
+
+    // Log and leave out a workflow run whose jobs cannot be fetched instead
+    // of failing
+    pub fn with_skip_errors(mut self, yes: bool) -> Self {
+        self.skip_errors = yes;
+        self
+    }
+
     fn reponame(&self) -> String {
         format!("{}/{}", self.owner, self.name)
     }
 
-    fn entrypoint_route(&self, run_id: String) -> String {
+    fn entrypoint_route(&self, run_id: String) -> crate::Result<String> {
         let param = Params {
             filter: Some("all".to_string()),
             ..Default::default()
         };
-        format!(
+        Ok(format!(
             "/repos/{owner}/{repo}/actions/runs/{run_id}/jobs?{query}",
             owner = &self.owner,
             repo = &self.name,
             run_id = &run_id,
-            query = param.to_query(),
-        )
+            query = param.to_query()?,
+        ))
     }
 
-    fn write_and_continue<T: std::io::Write>(
-        &self,
-        mut page: octocrab::Page<Job>,
-        wtr: &mut csv::Writer<T>,
-    ) -> Option<http::Uri> {
+    fn take_records(&self, page: &mut octocrab::Page<Job>) -> Vec<JobRec> {
         let labels: Vec<Job> = page.take_items();
-        for label in labels.into_iter() {
-            let mut label: JobRec = label.into();
-            label.set_repository(self.reponame());
-            wtr.serialize(&label).expect("Serialize failed");
+        labels
+            .into_iter()
+            .map(|label| {
+                let mut label: JobRec = label.into();
+                label.set_repository(self.reponame());
+                label
+            })
+            .collect()
+    }
+
+    async fn run_jobs(&self, run_id: String) -> crate::Result<Vec<JobRec>> {
+        let pages = pages::<Job>(&self.octocrab, self.entrypoint_route(run_id)?);
+        pin_mut!(pages);
+        let mut jobs = vec![];
+        while let Some(mut page) = pages.try_next().await? {
+            jobs.extend(self.take_records(&mut page));
         }
-        page.next.map(to_relative_uri)
+        Ok(jobs)
     }
 
-    pub async fn fetch<T: std::io::Write>(
-        &self,
-        mut wtr: csv::Writer<T>,
-        run_id: Option<String>,
-    ) -> octocrab::Result<()> {
-        if let Some(run_id_) = run_id {
-            let first: octocrab::Page<Job> = self
-                .octocrab
-                .get(self.entrypoint_route(run_id_), None::<&()>)
-                .await?;
-            let mut next = self.write_and_continue(first, &mut wtr);
-
-            while let Some(page) = self.octocrab.get_page(&next).await? {
-                next = self.write_and_continue(page, &mut wtr);
-            }
-        } else {
-            let run_fetcher = RunFetcher::new(
-                self.owner.clone(),
-                self.name.clone(),
-                self.since.clone(),
-                self.octocrab.clone(),
-            );
-            for workflow in self
-                .octocrab
-                .workflows(&self.owner, &self.name)
-                .list()
-                .send()
-                .await?
-            {
-                let mut last_update: Option<DateTime> = None;
-                let runs_first: octocrab::Page<Run> = self
-                    .octocrab
-                    .get(
-                        run_fetcher.entrypoint_route(Some(workflow.id.to_string())),
-                        None::<&()>,
-                    )
-                    .await?;
-                let mut run_page_opt = Some(runs_first);
-                while let Some(mut page) = run_page_opt {
+    pub fn stream(&self, run_id: Option<String>) -> impl Stream<Item = crate::Result<JobRec>> + '_ {
+        try_stream! {
+            if let Some(run_id_) = run_id {
+                let route = self.entrypoint_route(run_id_)?;
+                let pages = self.cursor.pages::<Job>(&self.octocrab, route);
+                pin_mut!(pages);
+                while let Some(mut page) = pages.try_next().await? {
+                    for record in self.take_records(&mut page) {
+                        yield record;
+                    }
+                }
+            } else {
+                // Walks the runs of the repository rather than of each
+                // workflow, so that the cursor follows a single listing
+                let run_fetcher = RunFetcher::new(
+                    self.owner.clone(),
+                    self.name.clone(),
+                    self.since,
+                    self.octocrab.clone(),
+                );
+                let route = run_fetcher.entrypoint_route(None)?;
+                let runs = self.cursor.pages::<Run>(&self.octocrab, route);
+                pin_mut!(runs);
+                while let Some(mut page) = runs.try_next().await? {
+                    let mut last_update: Option<DateTime> = None;
                     let runs: Vec<Run> = page.take_items();
                     for run in runs.into_iter() {
-                        let job_first: octocrab::Page<Job> = self
-                            .octocrab
-                            .get(self.entrypoint_route(run.id.to_string()), None::<&()>)
-                            .await?;
-                        let mut job_url = self.write_and_continue(job_first, &mut wtr);
-                        while let Some(page) = self.octocrab.get_page(&job_url).await? {
-                            job_url = self.write_and_continue(page, &mut wtr);
+                        let jobs = self
+                            .run_jobs(run.id.to_string())
+                            .await
+                            .map_err(|e| Error::item(format!("jobs of run {}", run.id), e));
+                        for job in skip_failed(jobs, self.skip_errors)? {
+                            yield job;
                         }
                         last_update = Some(run.updated_at);
                     }
 
-                    let next = if let Some(since) = self.since {
-                        last_update.map_or_else(
-                            || None,
-                            |last| {
-                                if last < since {
-                                    None
-                                } else {
-                                    page.next.map(to_relative_uri)
-                                }
-                            },
-                        )
-                    } else {
-                        page.next.map(to_relative_uri)
-                    };
-                    run_page_opt = self.octocrab.get_page(&next).await?;
+                    if let Some(since) = self.since {
+                        if last_update.is_none_or(|last| last < since) {
+                            break;
+                        }
+                    }
                 }
             }
         }
+    }
 
-        Ok(())
+    pub async fn fetch<S: RecordSink<JobRec> + ?Sized>(
+        &self,
+        sink: &mut S,
+        run_id: Option<String>,
+    ) -> crate::Result<()> {
+        write_stream(self.stream(run_id), sink).await
     }
 }